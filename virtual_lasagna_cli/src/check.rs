@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use crate::types;
+
+/// one problem found by `inspect`.
+pub struct Problem {
+    /// e.g. "posts[3] (num = 4).created"
+    pub location: String,
+    pub message: String,
+    /// `fix` can repair this without guessing content.
+    pub fixable: bool,
+}
+
+fn post_location(index: usize, post: &toml::Value) -> String {
+    match post.get("num").and_then(|v| v.as_integer()) {
+        Some(n) => format!("posts[{}] (num = {})", index, n),
+        None => format!("posts[{}]", index),
+    }
+}
+
+fn parse_date(s: &str) -> Option<types::Date> {
+    chrono::prelude::DateTime::<chrono::FixedOffset>::parse_from_rfc3339(s)
+        .ok()
+        .map(|v| v.into())
+}
+
+fn posts(data: &toml::Value) -> &[toml::Value] {
+    match data.get("posts").and_then(|v| v.as_array()) {
+        Some(p) => p.as_slice(),
+        None => &[],
+    }
+}
+
+/// valid num, or `None`.
+fn num_of(post: &toml::Value) -> Option<u32> {
+    let n = post.get("num")?.as_integer()?;
+    if n < 1 || n > u32::MAX as i64 {
+        return None;
+    }
+    Some(n as u32)
+}
+
+fn largest_num(data: &toml::Value) -> u32 {
+    posts(data).iter().filter_map(num_of).max().unwrap_or(0)
+}
+
+/// checks schema semantics, not only its shape.
+/// works on raw value, so that broken posts can be pointed out one by one.
+pub fn inspect(data: &toml::Value) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut problem = |location: String, message: String, fixable: bool| {
+        problems.push(Problem {
+            location,
+            message,
+            fixable,
+        })
+    };
+
+    if data.get("user").and_then(|v| v.as_str()).is_none() {
+        problem(
+            "user".to_string(),
+            "is missing or not a string.".to_string(),
+            false,
+        );
+    }
+
+    let max_num = data.get("max_num").and_then(|v| v.as_integer());
+    let largest = largest_num(data);
+    match max_num {
+        None => problem(
+            "max_num".to_string(),
+            "is missing or not an integer.".to_string(),
+            true,
+        ),
+        Some(m) if m < largest as i64 => problem(
+            "max_num".to_string(),
+            format!("{} is smaller than largest num {}.", m, largest),
+            true,
+        ),
+        _ => {}
+    }
+
+    if data.get("posts").and_then(|v| v.as_array()).is_none() {
+        problem(
+            "posts".to_string(),
+            "is missing or not an array.".to_string(),
+            false,
+        );
+    }
+
+    let mut seen = HashMap::new();
+    let mut seen_uuid = HashMap::new();
+    for (i, post) in posts(data).iter().enumerate() {
+        let location = post_location(i, post);
+
+        match num_of(post) {
+            None => problem(
+                format!("{}.num", location),
+                "is missing or not a positive integer.".to_string(),
+                true,
+            ),
+            Some(n) => match seen.get(&n) {
+                Some(first) => problem(
+                    format!("{}.num", location),
+                    format!("{} is duplicated with posts[{}].", n, first),
+                    true,
+                ),
+                None => {
+                    seen.insert(n, i);
+                }
+            },
+        }
+
+        if post.get("content").and_then(|v| v.as_str()).is_none() {
+            problem(
+                format!("{}.content", location),
+                "is missing or not a string.".to_string(),
+                false,
+            );
+        }
+
+        let created = match post.get("created").and_then(|v| v.as_str()) {
+            None => {
+                problem(
+                    format!("{}.created", location),
+                    "is missing or not a string.".to_string(),
+                    false,
+                );
+                None
+            }
+            Some(s) => {
+                let d = parse_date(s);
+                if d.is_none() {
+                    problem(
+                        format!("{}.created", location),
+                        format!("{:?} is not a valid rfc3339 timestamp.", s),
+                        false,
+                    );
+                }
+                d
+            }
+        };
+
+        if let Some(v) = post.get("updated") {
+            match v.as_str().map(|s| (s, parse_date(s))) {
+                None => problem(
+                    format!("{}.updated", location),
+                    "is not a string.".to_string(),
+                    false,
+                ),
+                Some((s, None)) => problem(
+                    format!("{}.updated", location),
+                    format!("{:?} is not a valid rfc3339 timestamp.", s),
+                    false,
+                ),
+                Some((s, Some(u))) => {
+                    if matches!(created, Some(c) if u < c) {
+                        problem(
+                            format!("{}.updated", location),
+                            format!("{} is earlier than created.", s),
+                            false,
+                        );
+                    }
+                }
+            }
+        }
+
+        if let Some(v) = post.get("uuid") {
+            match v.as_str().map(uuid::Uuid::parse_str) {
+                Some(Ok(u)) => match seen_uuid.get(&u) {
+                    Some(first) => problem(
+                        format!("{}.uuid", location),
+                        format!("{} is duplicated with posts[{}].", u, first),
+                        true,
+                    ),
+                    None => {
+                        seen_uuid.insert(u, i);
+                    }
+                },
+                _ => problem(
+                    format!("{}.uuid", location),
+                    "is not a valid uuid.".to_string(),
+                    true,
+                ),
+            }
+        }
+
+        if let Some(v) = post.get("is_deleted") {
+            if v.as_bool().is_none() {
+                problem(
+                    format!("{}.is_deleted", location),
+                    "is not a boolean.".to_string(),
+                    false,
+                );
+            }
+        }
+
+        if let Some(v) = post.get("tags") {
+            let is_strings = v.as_array().is_some_and(|a| a.iter().all(|t| t.is_str()));
+            if !is_strings {
+                problem(
+                    format!("{}.tags", location),
+                    "is not an array of strings.".to_string(),
+                    false,
+                );
+            }
+        }
+
+        let revisions = post.get("revisions").and_then(|v| v.as_array());
+        if post.get("revisions").is_some() && revisions.is_none() {
+            problem(
+                format!("{}.revisions", location),
+                "is not an array.".to_string(),
+                false,
+            );
+        }
+        for (j, r) in revisions.into_iter().flatten().enumerate() {
+            if r.get("content").and_then(|v| v.as_str()).is_none() {
+                problem(
+                    format!("{}.revisions[{}].content", location, j),
+                    "is missing or not a string.".to_string(),
+                    false,
+                );
+            }
+            match r.get("replaced").and_then(|v| v.as_str()) {
+                Some(s) if parse_date(s).is_some() => {}
+                _ => problem(
+                    format!("{}.revisions[{}].replaced", location, j),
+                    "is missing or not a valid rfc3339 timestamp.".to_string(),
+                    false,
+                ),
+            }
+        }
+    }
+
+    problems
+}
+
+/// repairs fixable problems, and returns what was done.
+/// posts with missing or duplicated num get new nums after largest one,
+/// and ones with invalid or duplicated uuid get new uuids. content is never touched.
+pub fn fix(data: &mut toml::Value) -> Vec<String> {
+    let mut done = vec![];
+    let mut next = largest_num(data).max(
+        data.get("max_num")
+            .and_then(|v| v.as_integer())
+            .map(|v| v.clamp(0, u32::MAX as i64) as u32)
+            .unwrap_or(0),
+    );
+
+    let mut seen = std::collections::HashSet::new();
+    if let Some(posts) = data.get_mut("posts").and_then(|v| v.as_array_mut()) {
+        for (i, post) in posts.iter_mut().enumerate() {
+            let location = post_location(i, post);
+
+            if matches!(num_of(post), Some(n) if seen.insert(n)) {
+                continue;
+            }
+
+            if let Some(t) = post.as_table_mut() {
+                next += 1;
+                seen.insert(next);
+                t.insert("num".to_string(), toml::Value::Integer(next as i64));
+                done.push(format!("{}.num: renumbered to {}.", location, next));
+            }
+        }
+
+        let mut seen_uuid = std::collections::HashSet::new();
+        for (i, post) in posts.iter_mut().enumerate() {
+            let uuid = match post.get("uuid") {
+                Some(v) => v.as_str().map(uuid::Uuid::parse_str),
+                None => continue,
+            };
+            if matches!(uuid, Some(Ok(u)) if seen_uuid.insert(u)) {
+                continue;
+            }
+
+            let location = post_location(i, post);
+            if let Some(t) = post.as_table_mut() {
+                let u = uuid::Uuid::new_v4();
+                seen_uuid.insert(u);
+                t.insert("uuid".to_string(), toml::Value::String(u.to_string()));
+                done.push(format!("{}.uuid: replaced with {}.", location, u));
+            }
+        }
+    }
+
+    let max_num = data.get("max_num").and_then(|v| v.as_integer());
+    if !matches!(max_num, Some(m) if m >= next as i64) {
+        if let Some(t) = data.as_table_mut() {
+            t.insert("max_num".to_string(), toml::Value::Integer(next as i64));
+            done.push(format!("max_num: set to {}.", next));
+        }
+    }
+
+    done
+}
@@ -1,7 +1,11 @@
-use crate::{constant, schema, serde, types};
+use crate::{
+    check, config, constant, export, filter, import, journal, merge, output, repair, schema, serde,
+    storage, types,
+};
 
 pub fn nop() -> types::ExitStatus {
-    println!("no input detected. no-operated.");
+    textln!("no input detected. no-operated.");
+    output::emit(serde_json::json!(null));
     None
 }
 
@@ -12,73 +16,361 @@ pub fn command(s: String) -> types::ExitStatus {
     let mut args = match splitted {
         Ok(o) => o,
         Err(e) => {
-            println!("{}", e);
+            output::error(output::Failure::Usage, e);
             return None;
         }
     };
 
-    match args.remove(0) {
+    if args.is_empty() {
+        output::error(
+            output::Failure::Usage,
+            r#"no command supplied. see ":help"."#,
+        );
+        return None;
+    }
+
+    let name = args.remove(0);
+    run(name, args)
+}
+
+/// in: "[command]", [args...]
+pub fn run(name: &str, args: types::Args) -> types::ExitStatus {
+    match name {
         "exit" => exit(args),
         "help" => help(args),
+        "open" => open(args),
+        "format" => format(args),
+        "migrate" => migrate(args),
+        "repair" => repair(args),
+        "export" => export(args),
+        "import" => import(args),
+        "merge" => merge(args),
         "remove" => remove(args),
+        "restore" | "undelete" => restore(args),
+        "trash" => trash(args),
+        "purge" => purge(args),
         "edit" => edit(args),
+        "compose" => {
+            output::error(
+                output::Failure::Usage,
+                "compose is available only in interactive mode.",
+            );
+            None
+        }
+        "tag" => tag(args),
+        "tags" => tags(args),
         "check" => check(args),
         "init" => init(args),
         "show" => show(args),
+        "browse" => browse(args),
+        "search" => search(args),
+        "undo" => undo(args),
+        "redo" => redo(args),
+        "history" => history(args),
+        "revert" => revert(args),
         _ => {
-            println!(r#"unknown command. see ":help"."#);
+            output::error(output::Failure::Usage, r#"unknown command. see ":help"."#);
             None
         }
     }
 }
 
-fn show_command_parse(args: types::Args) -> anyhow::Result<(usize, usize), String> {
-    if args.len() != 2 {
+/// in: [once_show, page_num, "--tag", "idea", ...]
+fn show_command_parse(
+    args: types::Args,
+) -> anyhow::Result<(usize, usize, filter::Filter, filter::Order), String> {
+    if args.len() < 2 {
         return Err(format!(
-            "excepted 2 args, but supplied {} args.",
+            "excepted 2 and more args, but supplied {} args.",
             args.len()
         ));
     }
 
-    let once_show = match args[0].parse() {
+    let once_show = once_show_parse(args[0])?;
+
+    let page_num = match args[1].parse() {
         Ok(n) => {
             if n == 0 {
-                return Err("parse error (once_show): cannot specify 0 or less".to_string());
+                return Err("parse error (page_num): cannot specify 0 or less".to_string());
             } else {
                 n
             }
         }
-        Err(e) => return Err(format!("parse error (once_show): {}", e)),
+        Err(e) => return Err(format!("parse error (page_num): {}", e)),
     };
 
-    let page_num = match args[1].parse() {
+    let (f, order) = filter_args_parse(&args[2..])?;
+
+    Ok((once_show, page_num, f, order))
+}
+
+fn once_show_parse(arg: &str) -> anyhow::Result<usize, String> {
+    match arg.parse() {
         Ok(n) => {
             if n == 0 {
-                return Err("parse error (page_num): cannot specify 0 or less".to_string());
+                Err("parse error (once_show): cannot specify 0 or less".to_string())
             } else {
-                n
+                Ok(n)
             }
         }
-        Err(e) => return Err(format!("parse error (page_num): {}", e)),
-    };
+        Err(e) => Err(format!("parse error (once_show): {}", e)),
+    }
+}
+
+/// posts which match `f`, sorted by `order`.
+fn select_posts(
+    data: &mut schema::Schema,
+    f: &filter::Filter,
+    order: &filter::Order,
+) -> Vec<schema::Post> {
+    let mut selected = data
+        .posts
+        .drain(..)
+        .filter(|v| f.is_match(v))
+        .collect::<Vec<_>>();
+
+    order.sort(selected.as_mut_slice());
+    selected
+}
+
+/// count of pages. empty list still has one (empty) page.
+pub fn page_count(total: usize, once_show: usize) -> usize {
+    total.div_ceil(once_show).max(1)
+}
+
+/// range of `page_num`th page (1-origin). last page may be shorter than `once_show`.
+pub fn page_range(
+    total: usize,
+    once_show: usize,
+    page_num: usize,
+) -> anyhow::Result<std::ops::Range<usize>, String> {
+    let pages = page_count(total, once_show);
+    if page_num == 0 || pages < page_num {
+        return Err(format!(
+            "out of range: page {} of {} pages ({} posts).",
+            page_num, pages, total
+        ));
+    }
 
-    Ok((once_show, page_num))
+    let start = once_show * (page_num - 1);
+    Ok(start..(start + once_show).min(total))
 }
 
 fn show(args: types::Args) -> types::ExitStatus {
-    let (once_show, page_num) = match show_command_parse(args) {
+    let (once_show, page_num, f, order) = match show_command_parse(args) {
         Ok(n) => n,
         Err(e) => {
-            println!("{}", e);
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+    };
+
+    let mut s = load()?;
+
+    textln!("user: {}", s.user);
+    textln!("max_num: {}", s.max_num);
+    textln!("once_show: {} | page_num: {}", once_show, page_num);
+
+    let mut tmp_vec = select_posts(&mut s, &f, &order);
+    let total = tmp_vec.len();
+
+    let show_range = match page_range(total, once_show, page_num) {
+        Ok(r) => r,
+        Err(e) => {
+            output::error(output::Failure::NotFound, e);
+            None?
+        }
+    };
+    textln!(
+        "show: {:?} in {} posts | page: {} / {}",
+        show_range,
+        total,
+        page_num,
+        page_count(total, once_show)
+    );
+
+    textln!();
+
+    let shown = tmp_vec.drain(show_range).collect::<Vec<_>>();
+    shown.iter().for_each(print_post);
+
+    output::emit(serde_json::json!({
+        "user": s.user,
+        "max_num": s.max_num,
+        "once_show": once_show,
+        "page_num": page_num,
+        "pages": page_count(total, once_show),
+        "total": total,
+        "posts": shown.iter().map(post_json).collect::<Vec<_>>(),
+    }));
+
+    None
+}
+
+/// in: [once_show (optional), "--tag", "idea", ...]
+fn browse_command_parse(
+    args: types::Args,
+) -> anyhow::Result<(usize, filter::Filter, filter::Order), String> {
+    let (once_show, rest) = match args.first() {
+        Some(a) if !a.starts_with("--") => (once_show_parse(a)?, &args[1..]),
+        _ => (constant::DEFAULT_PER_PAGE, &args[..]),
+    };
+    let (f, order) = filter_args_parse(rest)?;
+
+    Ok((once_show, f, order))
+}
+
+/// pages through posts interactively, until "q" or end of input.
+fn browse(args: types::Args) -> types::ExitStatus {
+    let (once_show, f, order) = match browse_command_parse(args) {
+        Ok(t) => t,
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
             None?
         }
     };
 
-    let mut s = serde::de_inner().unwrap();
+    if !output::is_text() {
+        output::error(
+            output::Failure::Usage,
+            r#"browse is interactive, use "show" in json format."#,
+        );
+        None?
+    }
+
+    let mut s = load()?;
+    let posts = select_posts(&mut s, &f, &order);
+    let pages = page_count(posts.len(), once_show);
+
+    let stdin = std::io::stdin();
+    let mut page_num = 1;
+    loop {
+        let range = page_range(posts.len(), once_show, page_num).unwrap();
+        textln!(
+            "page: {} / {} | show: {:?} in {} posts",
+            page_num,
+            pages,
+            range,
+            posts.len()
+        );
+        textln!();
+        posts[range].iter().for_each(print_post);
+
+        eprint!("[n]ext, [p]rev, [page num] or [q]uit: ");
+        let mut buf = String::new();
+        match stdin.read_line(&mut buf) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                output::error(
+                    output::Failure::Usage,
+                    format!("failed reading input, error: {}", e),
+                );
+                break;
+            }
+        }
+
+        match buf.trim() {
+            "" | "n" if page_num < pages => page_num += 1,
+            "" | "n" => textln!("already last page."),
+            "p" if 1 < page_num => page_num -= 1,
+            "p" => textln!("already first page."),
+            "q" => break,
+            k => match k.parse::<usize>() {
+                Ok(n) if (1..=pages).contains(&n) => page_num = n,
+                Ok(n) => textln!("out of range: page {} of {} pages.", n, pages),
+                Err(_) => textln!("unknown key: {:?}", k),
+            },
+        }
+    }
+
+    textln!("browse finished.");
+    None
+}
+
+/// same structure as stored in toml file.
+fn post_json(v: &schema::Post) -> serde_json::Value {
+    serde_json::to_value(serde::post_to_dfsd(v.clone())).unwrap()
+}
+
+fn print_post(v: &schema::Post) {
+    textln!(
+        "num: {} | uuid: {} | created: {} | updated {:?}",
+        v.num,
+        v.uuid,
+        serde::fmt_date(&v.created),
+        v.updated.as_ref().map(serde::fmt_date)
+    );
+    if !v.tags.is_empty() {
+        textln!(
+            "tags: {}",
+            v.tags
+                .iter()
+                .map(|t| format!("#{}", t))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+    }
+    textln!("content:");
+    textln!("{}", v.content);
+    textln!();
+}
+
+pub enum SearchMode {
+    Substring(String),
+    CaseInsensitive(String),
+    Regex(regex::Regex),
+}
+
+impl SearchMode {
+    pub fn is_match(&self, content: &str) -> bool {
+        match self {
+            SearchMode::Substring(q) => content.contains(q.as_str()),
+            SearchMode::CaseInsensitive(q) => content.to_lowercase().contains(q.as_str()),
+            SearchMode::Regex(r) => r.is_match(content),
+        }
+    }
+}
+
+pub fn search_command_parse(mut args: types::Args) -> anyhow::Result<(SearchMode, String), String> {
+    if args.len() <= 1 {
+        return Err(format!(
+            "excepted 2 and more args, but supplied {} args.",
+            args.len()
+        ));
+    }
+
+    let mode = args.remove(0);
+    let query = args.join(" ");
+
+    let matcher = match mode {
+        "sub" => SearchMode::Substring(query.clone()),
+        "isub" => SearchMode::CaseInsensitive(query.to_lowercase()),
+        "regex" => match regex::Regex::new(query.as_str()) {
+            Ok(r) => SearchMode::Regex(r),
+            Err(e) => return Err(format!("parse error (query): {}", e)),
+        },
+        _ => {
+            return Err(format!(
+                r#"unknown mode: "{}" (excepted "sub", "isub" or "regex")."#,
+                mode
+            ))
+        }
+    };
+
+    Ok((matcher, query))
+}
+
+fn search(args: types::Args) -> types::ExitStatus {
+    let (matcher, query) = match search_command_parse(args) {
+        Ok(t) => t,
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+    };
 
-    println!("user: {}", s.user);
-    println!("max_num: {}", s.max_num);
-    println!("once_show: {} | page_num: {}", once_show, page_num);
+    let mut s = load()?;
 
     let mut tmp_vec = s
         .posts
@@ -87,83 +379,80 @@ fn show(args: types::Args) -> types::ExitStatus {
             None => true,
             Some(b) => !b,
         })
-        .collect::<smallvec::SmallVec<[_; 1024]>>();
+        .filter(|v| matcher.is_match(v.content.as_str()))
+        .collect::<Vec<_>>();
 
-    tmp_vec.sort_by(|v1, v2| v1.num.cmp(&v2.num));
+    tmp_vec.sort_by_key(|v| v.num);
 
-    let paging_offset = once_show * (page_num - 1);
-    // (0 + paging_offset)..(once_show + paging_offset);
-    let show_range = paging_offset..(once_show + paging_offset);
-    println!("show: {}..{} | end_index: {}", show_range.start, show_range.end - 1, tmp_vec.len() - 1);
-
-    if tmp_vec.len() <= show_range.start || tmp_vec.len() < show_range.end {
-        println!("out of range: {:?} in {:?}", show_range, 0..tmp_vec.len());
-        None?
-    }
+    textln!("query: {:?} | matched: {}", query, tmp_vec.len());
+    textln!();
 
-    println!();
+    tmp_vec.iter().for_each(print_post);
 
-    tmp_vec.drain(show_range).for_each(|v| {
-        println!(
-            "num: {} | created: {} | updated {:?}",
-            v.num, v.created, v.updated
-        );
-        println!("content:");
-        println!("{}", v.content);
-        println!();
-    });
+    output::emit(serde_json::json!({
+        "query": query,
+        "matched": tmp_vec.len(),
+        "posts": tmp_vec.iter().map(post_json).collect::<Vec<_>>(),
+    }));
 
     None
 }
 
 fn exit(_: types::Args) -> types::ExitStatus {
-    println!("args are ignored, exiting...");
+    textln!("args are ignored, exiting...");
+    output::emit(serde_json::json!({ "exit": 0 }));
     Some(0)
 }
 
 fn help(_: types::Args) -> types::ExitStatus {
-    println!(
+    textln!(
         r#"args are ignored, showing HELP_TEXT...
 
 {}
 "#,
         *constant::HELP_TEXT
     );
+    output::emit(serde_json::json!({ "help": *constant::HELP_TEXT }));
     None
 }
 
-fn init(args: types::Args) -> types::ExitStatus {
-    let user = match init_command_parse(args) {
-        Ok(n) => n,
-        Err(e) => {
-            println!("{}", e);
-            return None;
-        }
+fn open_command_parse(args: types::Args) -> anyhow::Result<String, String> {
+    if args.is_empty() {
+        return Err("excepted 1 and more args, but supplied 0 args.".to_string());
     }
-    .to_string();
-
-    let init_data = schema::Schema {
-        user,
-        max_num: 0,
-        posts: smallvec::smallvec![],
-    };
 
-    serde::ser(init_data);
+    // path may contain spaces.
+    Ok(args.join(" "))
+}
 
-    println!("successfully initialized file, will continue to check file integrity...");
-    match serde::de_inner() {
-        Ok(_) => {
-            println!("checked file integrity!");
-            None
-        }
+fn open(args: types::Args) -> types::ExitStatus {
+    let path = match open_command_parse(args) {
+        Ok(p) => p,
         Err(e) => {
-            println!("failed checking file integrity, error: {}", e);
-            Some(1)
+            output::error(output::Failure::Usage, e);
+            None?
         }
+    };
+
+    config::set_toml_path(path);
+    let path = config::toml_path();
+
+    if path.exists() {
+        textln!("switched to {}.", path.display());
+    } else {
+        textln!(
+            r#"switched to {}, but not exists yet. run ":init" to create."#,
+            path.display()
+        );
     }
+    output::emit(serde_json::json!({
+        "path": path.to_string_lossy(),
+        "exists": path.exists(),
+    }));
+    None
 }
 
-fn init_command_parse(args: types::Args) -> anyhow::Result<&str, String> {
+fn format_command_parse(args: types::Args) -> anyhow::Result<output::Format, String> {
     if args.len() != 1 {
         return Err(format!(
             "excepted 1 args, but supplied {} args.",
@@ -171,159 +460,1394 @@ fn init_command_parse(args: types::Args) -> anyhow::Result<&str, String> {
         ));
     }
 
-    Ok(args[0])
-}
-
-fn check(_: types::Args) -> types::ExitStatus {
-    println!("args are ignored, checking...");
-
-    match serde::de_inner() {
-        Ok(_) => println!("no error detected."),
-        Err(e) => println!("error: {}", e),
+    match args[0] {
+        "text" => Ok(output::Format::Text),
+        "json" => Ok(output::Format::Json),
+        f => Err(format!(
+            r#"unknown format: "{}" (excepted "text" or "json")."#,
+            f
+        )),
     }
-    None
-}
-
-fn split_command(raw: &str) -> anyhow::Result<types::Args, &str> {
-    let args = raw
-        .split(' ')
-        .into_iter()
-        .filter(|v| !v.is_empty())
-        .collect::<smallvec::SmallVec<_>>();
-
-    Ok(args)
 }
 
-pub fn post(s: String) -> types::ExitStatus {
-    let mut data = serde::de();
-
-    let post = schema::Post::new(s, data.max_num + 1);
-    data.posts.push(post.clone());
-    data.max_num += 1;
+fn format(args: types::Args) -> types::ExitStatus {
+    let f = match format_command_parse(args) {
+        Ok(f) => f,
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+    };
 
-    serde::ser(data);
+    output::set_format(f);
 
-    println!("successfully post: {:?}", post);
+    textln!("switched to text format.");
+    output::emit(serde_json::json!({ "format": "json" }));
     None
 }
 
-fn search_post(data: &schema::Schema, num: u32) -> anyhow::Result<usize, String> {
-    let searched = data
-        .posts
-        .iter()
-        .enumerate()
-        .filter(|(_, p)| p.num == num)
-        .map(|(i, _)| i)
-        .collect::<smallvec::SmallVec<[_; 4]>>();
-
-    if searched.len() != 1 {
-        Err(format!("excepted 1 match, but {} matched.", searched.len()))
-    } else {
-        Ok(searched[0])
+fn migrate_command_parse(args: types::Args) -> anyhow::Result<(String, String), String> {
+    if args.len() != 2 {
+        return Err(format!(
+            "excepted 2 args, but supplied {} args.",
+            args.len()
+        ));
     }
+
+    Ok((args[0].to_string(), args[1].to_string()))
 }
 
-/// in: [arg, arg, arg...]
-fn remove(args: types::Args) -> types::ExitStatus {
-    let num = match remove_command_parse(args) {
+fn migrate(args: types::Args) -> types::ExitStatus {
+    let (from, to) = match migrate_command_parse(args) {
+        Ok(t) => t,
         Err(e) => {
-            println!("{}", e);
+            output::error(output::Failure::Usage, e);
             None?
         }
-        Ok(n) => n,
     };
 
-    let mut data = serde::de();
+    let (from_path, to_path) = (
+        std::path::Path::new(from.as_str()),
+        std::path::Path::new(to.as_str()),
+    );
+
+    // locking same file twice never returns.
+    if config::canonical_path(from_path) == config::canonical_path(to_path) {
+        output::error(
+            output::Failure::Usage,
+            format!("cannot migrate {} to itself.", from),
+        );
+        None?
+    }
+
+    let mut src = storage::open(from_path);
+    let mut dest = storage::open(to_path);
 
-    let searched = search_post(&data, num);
+    let _src_lock = storage_result(src.lock(), "locking")?;
+    let _dest_lock = storage_result(dest.lock(), "locking")?;
 
-    let index = match searched {
-        Err(e) => {
-            println!("{}", e);
+    let data = storage_result(src.load(), "loading")?;
+    let j = storage_result(src.load_journal(), "loading journal of")?;
+
+    // never overwrite other journal.
+    if let Ok(d) = dest.load() {
+        if !d.posts.is_empty() {
+            output::error(
+                output::Failure::Conflict,
+                format!("{} already has {} posts, not migrated.", to, d.posts.len()),
+            );
             None?
         }
-        Ok(i) => i,
-    };
+    }
 
-    let post = data.posts.get_mut(index).unwrap();
-    let num = post.num;
+    let count = data.posts.len();
+    storage_result(dest.save(data), "saving")?;
+    storage_result(dest.save_journal(&j), "saving journal of")?;
 
-    if post.is_deleted.is_some() && post.is_deleted.unwrap() {
-        println!("already deleted {}th post.", num);
+    textln!(
+        "successfully migrate {} posts from {} to {}.",
+        count,
+        from,
+        to
+    );
+    output::emit(serde_json::json!({
+        "from": from,
+        "to": to,
+        "posts": count,
+    }));
+    None
+}
+
+fn repair(_: types::Args) -> types::ExitStatus {
+    textln!("args are ignored, repairing...");
+
+    let path = config::toml_path();
+    if !storage::is_toml(&path) {
+        output::error(
+            output::Failure::Usage,
+            format!("{} is not toml file, nothing to repair.", path.display()),
+        );
+        None?
+    }
+
+    let _lock = storage_result(serde::lock(&path), "locking")?;
+
+    if serde::de(&path).is_ok() {
+        textln!("no broken post detected, nothing to repair.");
+        output::emit(serde_json::json!({ "salvaged": null, "quarantined": [] }));
         None?
     }
-    post.is_deleted = Some(true);
-    post.updated = Some(chrono::Local::now());
 
-    serde::ser(data);
+    let text = storage_result(std::fs::read_to_string(&path), "reading")?;
+    let r = storage_result(repair::repair(text.as_str()), "repairing")?;
 
-    println!("successfully delete {}th post.", num);
+    let sidecar = path.with_extension("quarantine.toml");
+    if !r.quarantined.is_empty() {
+        storage_result(repair::quarantine(&sidecar, &r.quarantined), "quarantining")?;
+    }
+    storage_result(serde::ser(&path, r.data), "saving")?;
+    // salvaged posts may be renumbered.
+    clear_journal();
+
+    for q in r.quarantined.iter() {
+        textln!("quarantined: line {}: {}", q.line, q.reason);
+    }
+    for v in r.fixed.iter() {
+        textln!("fixed: {}", v);
+    }
+    textln!(
+        "successfully salvaged {} posts, {} broken blocks are moved to {}.",
+        r.salvaged,
+        r.quarantined.len(),
+        sidecar.display()
+    );
+    output::emit(serde_json::json!({
+        "salvaged": r.salvaged,
+        "quarantined": r.quarantined.iter().map(|q| serde_json::json!({
+            "line": q.line,
+            "reason": q.reason,
+        })).collect::<Vec<_>>(),
+        "quarantine_path": sidecar.to_string_lossy(),
+        "fixed": r.fixed,
+    }));
     None
 }
 
-fn remove_command_parse(args: types::Args) -> anyhow::Result<u32, String> {
-    if args.len() != 1 {
+/// in: [format, path, "--since", "2020-01-01", "--to=10", ...]
+fn export_command_parse(
+    args: types::Args,
+) -> anyhow::Result<(export::Format, String, filter::Filter, filter::Order), String> {
+    if args.len() < 2 {
         return Err(format!(
-            "excepted 1 args, but supplied {} args.",
+            "excepted 2 and more args, but supplied {} args.",
             args.len()
         ));
     }
 
-    match args[0].parse() {
-        Err(e) => Err(format!("{}", e)),
-        Ok(n) => Ok(n),
+    let format = args[0].parse()?;
+    let path = args[1].to_string();
+    let (f, order) = filter_args_parse(&args[2..])?;
+
+    Ok((format, path, f, order))
+}
+
+/// in: ["--since", "2020-01-01", "--to=10", "--tag", "idea", "--reverse", ...]
+fn filter_args_parse(args: &[&str]) -> anyhow::Result<(filter::Filter, filter::Order), String> {
+    let mut f = filter::Filter::default();
+    let mut order = filter::Order::default();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if f.set_flag(arg) || order.set_flag(arg) {
+            continue;
+        }
+
+        let (key, value) = match arg.split_once('=') {
+            Some((k, v)) => (k, Some(v)),
+            None => (*arg, rest.next().copied()),
+        };
+        let value = match value {
+            Some(v) => v,
+            None => return Err(format!("{} requires value.", key)),
+        };
+
+        if !f.set(key, value)? && !order.set(key, value)? {
+            return Err(format!("unknown argument: {}", key));
+        }
     }
+
+    Ok((f, order))
 }
 
-/// in: [arg, arg, arg...]
-fn edit(args: types::Args) -> types::ExitStatus {
-    let (num, new_content) = match edit_command_parse(args) {
+fn export(args: types::Args) -> types::ExitStatus {
+    let (format, path, f, order) = match export_command_parse(args) {
+        Ok(t) => t,
         Err(e) => {
-            println!("{}", e);
+            output::error(output::Failure::Usage, e);
             None?
         }
-        Ok(t) => t,
     };
 
-    let mut data = serde::de();
+    let data = load()?;
 
-    let searched = search_post(&data, num);
+    let mut posts = data
+        .posts
+        .iter()
+        .filter(|v| f.is_match(v))
+        .collect::<Vec<_>>();
+    order.sort(posts.as_mut_slice());
 
-    let index = match searched {
+    let s = storage_result(
+        export::render(&format, data.user.as_str(), posts.as_slice()),
+        "rendering",
+    )?;
+    storage_result(
+        serde::write_atomic(std::path::Path::new(path.as_str()), s.as_bytes()),
+        "writing",
+    )?;
+
+    textln!("successfully export {} posts to {}.", posts.len(), path);
+    output::emit(serde_json::json!({
+        "path": path,
+        "posts": posts.len(),
+    }));
+    None
+}
+
+fn import(args: types::Args) -> types::ExitStatus {
+    // path may contain spaces.
+    let path = match open_command_parse(args) {
+        Ok(p) => p,
         Err(e) => {
-            println!("{}", e);
+            output::error(output::Failure::Usage, e);
             None?
         }
-        Ok(i) => i,
     };
 
-    let post = data.posts.get_mut(index).unwrap();
+    let imported = match import::read(std::path::Path::new(path.as_str())) {
+        Ok(v) => v,
+        Err(e) => {
+            output::error(
+                output::Failure::Usage,
+                format!("failed reading {}, error: {}", path, e),
+            );
+            None?
+        }
+    };
 
-    post.content = new_content;
-    post.updated = Some(chrono::Local::now());
+    let _lock = lock()?;
+    let mut data = load()?;
 
-    serde::ser(data);
+    let mut hashes = data
+        .posts
+        .iter()
+        .map(|v| serde::hash_of(v.content.as_bytes()))
+        .collect::<std::collections::HashSet<_>>();
+    let mut added = vec![];
+    let mut skipped = 0;
 
-    println!("successfully edit {}th post.", index);
+    for v in imported {
+        if v.content.trim().is_empty() || !hashes.insert(serde::hash_of(v.content.as_bytes())) {
+            skipped += 1;
+            continue;
+        }
 
-    None
-}
+        let mut post = schema::Post::new(v.content, data.max_num + 1);
+        if let Some(c) = v.created {
+            post.created = c;
+        }
+        data.max_num = post.num;
+        data.posts.push(post.clone());
+        added.push(post);
+    }
 
-fn edit_command_parse(mut args: types::Args) -> anyhow::Result<(u32, String), String> {
-    if args.len() <= 1 {
-        return Err(format!(
-            "excepted 2 and more args, bug supplied {} args.",
-            args.len()
-        ));
+    if !added.is_empty() {
+        save(data)?;
     }
 
-    let num = match args.remove(0).parse() {
-        Err(e) => return Err(format!("{}", e)),
-        Ok(n) => n,
-    };
+    // undone at once.
+    if !added.is_empty() {
+        record(journal::Entry {
+            kind: "import".to_string(),
+            changes: added
+                .iter()
+                .map(|v| journal::Change {
+                    num: v.num,
+                    before: None,
+                    after: Some(serde::post_to_dfsd(v.clone())),
+                })
+                .collect(),
+        });
+    }
 
-    let mut content = String::new();
-    args.drain(..).for_each(|v| content += v);
+    textln!(
+        "successfully import {} posts from {}. ({} empty or duplicated posts are skipped)",
+        added.len(),
+        path,
+        skipped
+    );
+    output::emit(serde_json::json!({
+        "imported": added.iter().map(|v| v.num).collect::<Vec<_>>(),
+        "skipped": skipped,
+    }));
+    None
+}
 
-    Ok((num, content))
+fn merge(args: types::Args) -> types::ExitStatus {
+    // path may contain spaces.
+    let path = match open_command_parse(args) {
+        Ok(p) => p,
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+    };
+
+    let other_path = std::path::Path::new(path.as_str());
+
+    // locking current file twice never returns.
+    if config::canonical_path(other_path) == config::canonical_path(&config::toml_path()) {
+        output::error(
+            output::Failure::Usage,
+            format!("cannot merge {} into itself.", path),
+        );
+        None?
+    }
+
+    let _lock = lock()?;
+    let mut data = load()?;
+
+    let mut other = storage::open(other_path);
+    let _other_lock = storage_result(other.lock(), "locking")?;
+    let theirs = storage_result(other.load(), "loading")?;
+
+    let report = merge::merge(&mut data, theirs);
+
+    if !report.changes.is_empty() {
+        save(data)?;
+    }
+
+    // undone at once, as same as import.
+    if !report.changes.is_empty() {
+        record(journal::Entry {
+            kind: "merge".to_string(),
+            changes: report
+                .changes
+                .iter()
+                .map(|(before, after)| journal::Change {
+                    num: after.num,
+                    before: before.clone().map(serde::post_to_dfsd),
+                    after: Some(serde::post_to_dfsd(after.clone())),
+                })
+                .collect(),
+        });
+    }
+
+    let added = report
+        .changes
+        .iter()
+        .filter(|(b, _)| b.is_none())
+        .map(|(_, a)| a.num)
+        .collect::<Vec<_>>();
+    let updated = report
+        .changes
+        .iter()
+        .filter(|(b, _)| b.is_some())
+        .map(|(_, a)| a.num)
+        .collect::<Vec<_>>();
+
+    for c in report.conflicts.iter() {
+        textln!(
+            "conflict: {}th post (={}th in {}): {}",
+            c.num,
+            c.other_num,
+            path,
+            c.reason
+        );
+    }
+    textln!(
+        "successfully merge {}. added: {:?}, updated: {:?}, already same: {}, conflicts: {} (kept local).",
+        path,
+        added,
+        updated,
+        report.same,
+        report.conflicts.len()
+    );
+    output::emit(serde_json::json!({
+        "added": added,
+        "updated": updated,
+        "same": report.same,
+        "conflicts": report.conflicts.iter().map(|c| serde_json::json!({
+            "num": c.num,
+            "other_num": c.other_num,
+            "reason": c.reason,
+        })).collect::<Vec<_>>(),
+    }));
+    None
+}
+
+fn init(args: types::Args) -> types::ExitStatus {
+    let user = match init_command_parse(args) {
+        Ok(n) => n,
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            return None;
+        }
+    }
+    .to_string();
+
+    let init_data = schema::Schema {
+        user: user.clone(),
+        max_num: 0,
+        posts: vec![],
+    };
+
+    let _lock = lock()?;
+    save(init_data)?;
+
+    textln!("successfully initialized file, will continue to check file integrity...");
+    match storage::with(|s| s.load()) {
+        Ok(_) => {
+            textln!("checked file integrity!");
+            output::emit(serde_json::json!({ "user": user }));
+            None
+        }
+        Err(e) => {
+            output::error(
+                output::Failure::Storage,
+                format!("failed checking file integrity, error: {}", e),
+            );
+            Some(1)
+        }
+    }
+}
+
+fn init_command_parse(args: types::Args) -> anyhow::Result<&str, String> {
+    if args.len() != 1 {
+        return Err(format!(
+            "excepted 1 args, but supplied {} args.",
+            args.len()
+        ));
+    }
+
+    Ok(args[0])
+}
+
+/// in: [] or ["--fix"]
+fn check_command_parse(args: types::Args) -> anyhow::Result<bool, String> {
+    match args.as_slice() {
+        [] => Ok(false),
+        ["--fix"] => Ok(true),
+        [a] => Err(format!("parse error (--fix): unknown argument {}", a)),
+        _ => Err(format!(
+            "excepted 0 or 1 args, but supplied {} args.",
+            args.len()
+        )),
+    }
+}
+
+fn check(args: types::Args) -> types::ExitStatus {
+    let is_fix = match check_command_parse(args) {
+        Ok(f) => f,
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+    };
+
+    textln!("checking...");
+
+    let _lock = if is_fix { lock()? } else { None };
+    let storage::Raw {
+        data: mut raw,
+        unreadable,
+    } = match storage::with(|s| s.load_raw()) {
+        Ok(r) => r,
+        Err(e) => {
+            output::error(output::Failure::Storage, format!("error: {}", e));
+            None?
+        }
+    };
+
+    let mut fixed = vec![];
+    if is_fix {
+        fixed = check::fix(&mut raw);
+    }
+    let mut problems = unreadable;
+    problems.extend(check::inspect(&raw));
+
+    for v in fixed.iter() {
+        textln!("fixed: {}", v);
+    }
+    for p in problems.iter() {
+        textln!("{}: {}", p.location, p.message);
+    }
+
+    // saved as it is, even if other problems remain. they are reported below.
+    if is_fix && !fixed.is_empty() {
+        storage_result(storage::with(|s| s.save_raw(&raw)), "saving")?;
+        // nums may be changed.
+        clear_journal();
+    }
+
+    if problems.is_empty() {
+        textln!("no error detected.");
+        output::emit(serde_json::json!({
+            "errors": [],
+            "fixed": fixed,
+        }));
+    } else {
+        let errors = problems
+            .iter()
+            .map(|p| {
+                serde_json::json!({
+                    "location": p.location,
+                    "message": p.message,
+                    "fixable": p.fixable,
+                })
+            })
+            .collect::<Vec<_>>();
+        output::error_with(
+            output::Failure::Storage,
+            format!(
+                "{} problems detected. ({} fixable by \"check --fix\")",
+                problems.len(),
+                problems.iter().filter(|p| p.fixable).count()
+            ),
+            serde_json::json!({ "errors": errors, "fixed": fixed }),
+        );
+    }
+    None
+}
+
+fn split_command(raw: &str) -> anyhow::Result<types::Args, &str> {
+    let args = raw
+        .split(' ')
+        .into_iter()
+        .filter(|v| !v.is_empty())
+        .collect::<smallvec::SmallVec<_>>();
+
+    Ok(args)
+}
+
+pub fn post(s: String) -> types::ExitStatus {
+    let _lock = lock()?;
+    let max_num = storage_result(storage::with(|s| s.max_num()), "loading")?;
+
+    let post = schema::Post::new(s, max_num + 1);
+
+    append(post.clone())?;
+
+    record(journal::Entry::single(
+        "post",
+        post.num,
+        None,
+        Some(serde::post_to_dfsd(post.clone())),
+    ));
+
+    textln!("successfully post: {:?}", post);
+    output::emit(serde_json::json!({ "post": post_json(&post) }));
+    None
+}
+
+/// reports storage error, and returns `None` to stop command.
+fn storage_result<T, E: std::fmt::Display>(r: Result<T, E>, action: &str) -> Option<T> {
+    match r {
+        Ok(v) => Some(v),
+        Err(e) => {
+            output::error(
+                output::Failure::Storage,
+                format!("failed {} file, error: {}", action, e),
+            );
+            None
+        }
+    }
+}
+
+/// holds lock while read-modify-write.
+fn lock() -> Option<Option<serde::Lock>> {
+    storage_result(storage::with(|s| s.lock()), "locking")
+}
+
+fn load() -> Option<schema::Schema> {
+    storage_result(storage::with(|s| s.load()), "loading")
+}
+
+fn save(data: schema::Schema) -> Option<()> {
+    storage_result(storage::with(|s| s.save(data)), "saving")
+}
+
+fn append(post: schema::Post) -> Option<()> {
+    storage_result(storage::with(|s| s.append(post)), "saving")
+}
+
+fn update(post: schema::Post) -> Option<()> {
+    storage_result(storage::with(|s| s.update(post)), "saving")
+}
+
+fn record(entry: journal::Entry) {
+    if let Err(e) = journal::record(entry) {
+        output::warn(
+            output::Failure::Storage,
+            format!("failed recording to journal, error: {}", e),
+        );
+    }
+}
+
+/// after mutation which can not be undone.
+fn clear_journal() {
+    if let Err(e) = journal::clear() {
+        output::warn(
+            output::Failure::Storage,
+            format!("failed clearing journal, error: {}", e),
+        );
+    }
+}
+
+fn search_post(data: &schema::Schema, target: &schema::PostRef) -> anyhow::Result<usize, String> {
+    let search = |target: &schema::PostRef| {
+        data.posts
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| target.is_match(p))
+            .map(|(i, _)| i)
+            .collect::<smallvec::SmallVec<[_; 4]>>()
+    };
+
+    let mut searched = search(target);
+    // digits only uuid prefix. ("1234" when there is no 1234th post)
+    if searched.is_empty() {
+        if let Some(t) = target.uuid_fallback() {
+            searched = search(&t);
+        }
+    }
+
+    if searched.len() != 1 {
+        Err(format!("excepted 1 match, but {} matched.", searched.len()))
+    } else {
+        Ok(searched[0])
+    }
+}
+
+/// in: [arg, arg, arg...]
+fn remove(args: types::Args) -> types::ExitStatus {
+    let target = match remove_command_parse(args) {
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+        Ok(n) => n,
+    };
+
+    let _lock = lock()?;
+    let mut data = load()?;
+
+    let searched = search_post(&data, &target);
+
+    let index = match searched {
+        Err(e) => {
+            output::error(output::Failure::NotFound, e);
+            None?
+        }
+        Ok(i) => i,
+    };
+
+    let post = data.posts.get_mut(index).unwrap();
+    let num = post.num;
+
+    if post.is_deleted.is_some() && post.is_deleted.unwrap() {
+        output::error(
+            output::Failure::Conflict,
+            format!("already deleted {}th post.", num),
+        );
+        None?
+    }
+    let before = serde::post_to_dfsd(post.clone());
+    post.is_deleted = Some(true);
+    post.updated = Some(chrono::Local::now());
+    let after = serde::post_to_dfsd(post.clone());
+
+    update(post.clone())?;
+
+    record(journal::Entry::single(
+        "remove",
+        num,
+        Some(before),
+        Some(after.clone()),
+    ));
+
+    textln!("successfully delete {}th post.", num);
+    output::emit(serde_json::json!({ "post": after }));
+    None
+}
+
+/// in: [arg, arg, arg...]
+fn restore(args: types::Args) -> types::ExitStatus {
+    let target = match remove_command_parse(args) {
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+        Ok(n) => n,
+    };
+
+    let _lock = lock()?;
+    let mut data = load()?;
+
+    let index = match search_post(&data, &target) {
+        Err(e) => {
+            output::error(output::Failure::NotFound, e);
+            None?
+        }
+        Ok(i) => i,
+    };
+
+    let post = data.posts.get_mut(index).unwrap();
+    let num = post.num;
+
+    if !post.is_deleted.unwrap_or(false) {
+        output::error(
+            output::Failure::Conflict,
+            format!("{}th post is not deleted.", num),
+        );
+        None?
+    }
+    let before = serde::post_to_dfsd(post.clone());
+    post.is_deleted = None;
+    post.updated = Some(chrono::Local::now());
+    let after = serde::post_to_dfsd(post.clone());
+
+    update(post.clone())?;
+
+    record(journal::Entry::single(
+        "restore",
+        num,
+        Some(before),
+        Some(after.clone()),
+    ));
+
+    textln!("successfully restore {}th post.", num);
+    output::emit(serde_json::json!({ "post": after }));
+    None
+}
+
+fn trash(_: types::Args) -> types::ExitStatus {
+    textln!("args are ignored, showing deleted posts...");
+
+    let mut s = load()?;
+
+    let mut tmp_vec = s
+        .posts
+        .drain(..)
+        .filter(|v| v.is_deleted.unwrap_or(false))
+        .collect::<Vec<_>>();
+
+    tmp_vec.sort_by_key(|v| v.num);
+
+    textln!("deleted: {}", tmp_vec.len());
+    textln!();
+
+    tmp_vec.iter().for_each(print_post);
+
+    output::emit(serde_json::json!({
+        "posts": tmp_vec.iter().map(post_json).collect::<Vec<_>>(),
+    }));
+
+    None
+}
+
+fn purge_command_parse(args: types::Args) -> anyhow::Result<Option<u32>, String> {
+    match args.len() {
+        0 => Ok(None),
+        1 => match args[0].parse() {
+            Err(e) => Err(format!("parse error (days): {}", e)),
+            Ok(n) => Ok(Some(n)),
+        },
+        n => Err(format!("excepted 0 or 1 args, but supplied {} args.", n)),
+    }
+}
+
+fn purge(args: types::Args) -> types::ExitStatus {
+    let days = match purge_command_parse(args) {
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+        Ok(n) => n,
+    };
+
+    // u32 days always fit in Duration. (chrono 0.4.19 has no `Duration::try_days`)
+    // but too old date does not.
+    let threshold = match days {
+        None => None,
+        Some(v) => {
+            match chrono::Local::now().checked_sub_signed(chrono::Duration::days(i64::from(v))) {
+                Some(t) => Some(t),
+                None => {
+                    output::error(
+                        output::Failure::Usage,
+                        format!("out of range: {} days ago.", v),
+                    );
+                    None?
+                }
+            }
+        }
+    };
+
+    let _lock = lock()?;
+    let mut data = load()?;
+
+    let mut purged = smallvec::SmallVec::<[u32; 64]>::new();
+    data.posts.retain(|v| {
+        let is_target = is_purgeable(v, threshold);
+
+        if is_target {
+            purged.push(v.num);
+        }
+        !is_target
+    });
+
+    if purged.is_empty() {
+        textln!("nothing to purge.");
+        output::emit(serde_json::json!({ "purged": [] }));
+        None?
+    }
+
+    save(data)?;
+
+    clear_journal();
+
+    textln!("successfully purge {} posts: {:?}", purged.len(), purged);
+    output::emit(serde_json::json!({ "purged": purged.as_slice() }));
+    None
+}
+
+/// removed before `threshold`, or removed at all if it is None.
+/// removed time is kept on `updated`, so posts without it are too old to know and kept.
+fn is_purgeable(p: &schema::Post, threshold: Option<types::Date>) -> bool {
+    if !p.is_deleted.unwrap_or(false) {
+        return false;
+    }
+
+    match (threshold, p.updated) {
+        (None, _) => true,
+        (Some(t), Some(u)) => u <= t,
+        (Some(_), None) => false,
+    }
+}
+
+fn remove_command_parse(args: types::Args) -> anyhow::Result<schema::PostRef, String> {
+    if args.len() != 1 {
+        return Err(format!(
+            "excepted 1 args, but supplied {} args.",
+            args.len()
+        ));
+    }
+
+    args[0].parse()
+}
+
+/// in: [arg, arg, arg...]
+fn edit(args: types::Args) -> types::ExitStatus {
+    let (target, new_content) = match edit_command_parse(args) {
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+        Ok(t) => t,
+    };
+
+    edit_post(target, new_content, None)
+}
+
+/// replaces content of `target` post. also used by compose mode.
+/// if `expected` is supplied, fails when current content is not it. (changed while composing)
+pub fn edit_post(
+    target: schema::PostRef,
+    new_content: String,
+    expected: Option<&str>,
+) -> types::ExitStatus {
+    let _lock = lock()?;
+    let mut data = load()?;
+
+    let searched = search_post(&data, &target);
+
+    let index = match searched {
+        Err(e) => {
+            output::error(output::Failure::NotFound, e);
+            None?
+        }
+        Ok(i) => i,
+    };
+
+    let post = data.posts.get_mut(index).unwrap();
+    let num = post.num;
+
+    if matches!(expected, Some(e) if e != post.content) {
+        output::error(
+            output::Failure::Conflict,
+            format!("{}th post was changed while editing, not edited.", num),
+        );
+        None?
+    }
+
+    let before = serde::post_to_dfsd(post.clone());
+    post.revise(new_content);
+    let after = serde::post_to_dfsd(post.clone());
+
+    update(post.clone())?;
+
+    record(journal::Entry::single(
+        "edit",
+        num,
+        Some(before),
+        Some(after.clone()),
+    ));
+
+    textln!("successfully edit {}th post.", num);
+    output::emit(serde_json::json!({ "post": after }));
+
+    None
+}
+
+/// current content of `target` post, to be edited in compose mode.
+/// post is returned as full uuid, which is not changed by renumbering while composing.
+pub fn content_of(target: &schema::PostRef) -> Option<(schema::PostRef, String)> {
+    let data = load()?;
+
+    match search_post(&data, target) {
+        Err(e) => {
+            output::error(output::Failure::NotFound, e);
+            None
+        }
+        Ok(i) => {
+            let p = &data.posts[i];
+            Some((
+                schema::PostRef::Uuid(p.uuid.simple().to_string()),
+                p.content.clone(),
+            ))
+        }
+    }
+}
+
+fn edit_command_parse(mut args: types::Args) -> anyhow::Result<(schema::PostRef, String), String> {
+    if args.len() <= 1 {
+        return Err(format!(
+            "excepted 2 and more args, bug supplied {} args.",
+            args.len()
+        ));
+    }
+
+    let num = args.remove(0).parse()?;
+
+    let mut content = String::new();
+    args.drain(..).for_each(|v| content += v);
+
+    Ok((num, content))
+}
+
+/// in: [Post#num, "+foo", "-bar", ...]
+/// out: (target, tags to add, tags to remove)
+fn tag_command_parse(
+    args: types::Args,
+) -> anyhow::Result<(schema::PostRef, Vec<String>, Vec<String>), String> {
+    if args.len() <= 1 {
+        return Err(format!(
+            "excepted 2 and more args, but supplied {} args.",
+            args.len()
+        ));
+    }
+
+    let target = args[0].parse()?;
+
+    let (mut add, mut remove) = (vec![], vec![]);
+    for arg in args[1..].iter() {
+        if let Some(t) = arg.strip_prefix('+') {
+            add.push(schema::impls::normalize_tag(t)?);
+        } else if let Some(t) = arg.strip_prefix('-') {
+            remove.push(schema::impls::normalize_tag(t)?);
+        } else {
+            return Err(format!(
+                r#"{:?} must start with "+" (add) or "-" (remove)."#,
+                arg
+            ));
+        }
+    }
+
+    Ok((target, add, remove))
+}
+
+fn tag(args: types::Args) -> types::ExitStatus {
+    let (target, add, remove) = match tag_command_parse(args) {
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+        Ok(t) => t,
+    };
+
+    let _lock = lock()?;
+    let mut data = load()?;
+
+    let index = match search_post(&data, &target) {
+        Err(e) => {
+            output::error(output::Failure::NotFound, e);
+            None?
+        }
+        Ok(i) => i,
+    };
+
+    let post = data.posts.get_mut(index).unwrap();
+    let num = post.num;
+
+    let before = serde::post_to_dfsd(post.clone());
+    for t in remove {
+        post.tags.remove(&t);
+    }
+    post.tags.extend(add);
+    let after = serde::post_to_dfsd(post.clone());
+
+    update(post.clone())?;
+
+    record(journal::Entry::single(
+        "tag",
+        num,
+        Some(before),
+        Some(after.clone()),
+    ));
+
+    textln!(
+        "successfully tag {}th post: {:?}",
+        num,
+        post.tags.iter().collect::<Vec<_>>()
+    );
+    output::emit(serde_json::json!({ "post": after }));
+
+    None
+}
+
+/// tags of not deleted posts, with how many posts have them.
+fn tags(_: types::Args) -> types::ExitStatus {
+    textln!("args are ignored, counting tags...");
+
+    let data = load()?;
+
+    let mut counts = std::collections::BTreeMap::<&str, usize>::new();
+    for t in data
+        .posts
+        .iter()
+        .filter(|v| v.is_deleted != Some(true))
+        .flat_map(|v| v.tags.iter())
+    {
+        *counts.entry(t.as_str()).or_default() += 1;
+    }
+
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    textln!("tags: {}", counts.len());
+    textln!();
+    for (t, n) in counts.iter() {
+        textln!("#{}: {}", t, n);
+    }
+
+    output::emit(serde_json::json!({
+        "tags": counts.iter().map(|(t, n)| serde_json::json!({
+            "tag": t,
+            "count": n,
+        })).collect::<Vec<_>>(),
+    }));
+    None
+}
+
+/// replaces post `expected` with `state`. `None` means "not exists".
+/// post is found by uuid, (by num for entries recorded before uuid) and must be exactly `expected`.
+/// otherwise it was changed after the entry was recorded, and nothing is applied.
+fn apply_post_state(
+    data: &mut schema::Schema,
+    num: u32,
+    expected: Option<schema::PostForSerde>,
+    state: Option<schema::PostForSerde>,
+) -> anyhow::Result<(), (output::Failure, String)> {
+    let uuid = state
+        .as_ref()
+        .or(expected.as_ref())
+        .and_then(|v| v.uuid.clone());
+    let index = data.posts.iter().position(|p| match &uuid {
+        Some(u) => p.uuid.to_string() == *u,
+        None => p.num == num,
+    });
+
+    let current = index.map(|i| serde::post_to_dfsd(data.posts[i].clone()));
+    let as_json = |v: &Option<schema::PostForSerde>| serde_json::to_value(v).ok();
+    if as_json(&current) != as_json(&expected) {
+        return Err((
+            output::Failure::Conflict,
+            format!("{}th post was changed after that, not applied.", num),
+        ));
+    }
+
+    let post = match state {
+        Some(p) => {
+            Some(serde::post_from_dfsd(p).map_err(|e| (output::Failure::Storage, e.to_string()))?)
+        }
+        None => None,
+    };
+
+    match (index, post) {
+        (Some(i), Some(p)) => data.posts[i] = p,
+        (Some(i), None) => {
+            data.posts.remove(i);
+        }
+        (None, Some(p)) => {
+            if data.posts.iter().any(|v| v.num == p.num) {
+                return Err((
+                    output::Failure::Conflict,
+                    format!("{}th post is used by other post, not applied.", p.num),
+                ));
+            }
+            if data.max_num < p.num {
+                data.max_num = p.num;
+            }
+            data.posts.push(p);
+        }
+        (None, None) => (),
+    }
+
+    Ok(())
+}
+
+/// applies every change of `entry`, or nothing if one of them conflicts.
+/// changes are undone in reverse order. returns states of changed posts.
+fn apply_entry(
+    data: &mut schema::Schema,
+    entry: &journal::Entry,
+    is_undo: bool,
+) -> anyhow::Result<Vec<Option<schema::PostForSerde>>, (output::Failure, String)> {
+    let mut applied = data.clone();
+    let mut states = vec![];
+
+    let changes: Box<dyn Iterator<Item = &journal::Change>> = if is_undo {
+        Box::new(entry.changes.iter().rev())
+    } else {
+        Box::new(entry.changes.iter())
+    };
+    for c in changes {
+        let (expected, state) = if is_undo {
+            (c.after.clone(), c.before.clone())
+        } else {
+            (c.before.clone(), c.after.clone())
+        };
+        apply_post_state(&mut applied, c.num, expected, state.clone())?;
+        states.push(state);
+    }
+
+    *data = applied;
+    Ok(states)
+}
+
+fn undo(_: types::Args) -> types::ExitStatus {
+    textln!("args are ignored, undoing...");
+    undo_or_redo(true)
+}
+
+fn redo(_: types::Args) -> types::ExitStatus {
+    textln!("args are ignored, redoing...");
+    undo_or_redo(false)
+}
+
+fn undo_or_redo(is_undo: bool) -> types::ExitStatus {
+    let name = if is_undo { "undo" } else { "redo" };
+
+    let _lock = lock()?;
+
+    let mut j = match journal::load() {
+        Ok(j) => j,
+        Err(e) => {
+            output::error(
+                output::Failure::Storage,
+                format!("failed loading journal, error: {}", e),
+            );
+            None?
+        }
+    };
+
+    let popped = if is_undo { j.undo.pop() } else { j.redo.pop() };
+    let entry = match popped {
+        Some(e) => e,
+        None => {
+            output::error(output::Failure::Conflict, format!("nothing to {}.", name));
+            None?
+        }
+    };
+
+    let mut data = load()?;
+
+    let states = match apply_entry(&mut data, &entry, is_undo) {
+        Ok(s) => s,
+        Err((kind, e)) => {
+            output::error(kind, e);
+            None?
+        }
+    };
+
+    save(data)?;
+
+    let (kind, nums) = (entry.kind.clone(), entry.nums());
+
+    if is_undo {
+        j.redo.push(entry);
+    } else {
+        j.undo.push(entry);
+    }
+    if let Err(e) = journal::save(&j) {
+        output::warn(
+            output::Failure::Storage,
+            format!("failed saving journal, error: {}", e),
+        );
+    }
+
+    match nums.as_slice() {
+        [num] => textln!("successfully {} {} of {}th post.", name, kind, num),
+        _ => textln!(
+            "successfully {} {} of {} posts: {:?}",
+            name,
+            kind,
+            nums.len(),
+            nums
+        ),
+    }
+    output::emit(serde_json::json!({
+        "kind": kind,
+        "nums": nums,
+        "posts": states,
+    }));
+
+    None
+}
+
+fn history_command_parse(args: types::Args) -> anyhow::Result<schema::PostRef, String> {
+    if args.len() != 1 {
+        return Err(format!(
+            "excepted 1 args, but supplied {} args.",
+            args.len()
+        ));
+    }
+
+    match args[0].parse() {
+        Err(e) => Err(format!("parse error (num): {}", e)),
+        Ok(n) => Ok(n),
+    }
+}
+
+fn history(args: types::Args) -> types::ExitStatus {
+    let target = match history_command_parse(args) {
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+        Ok(n) => n,
+    };
+
+    let data = load()?;
+
+    let index = match search_post(&data, &target) {
+        Err(e) => {
+            output::error(output::Failure::NotFound, e);
+            None?
+        }
+        Ok(i) => i,
+    };
+
+    let post = &data.posts[index];
+
+    textln!("num: {} | revisions: {}", post.num, post.revisions.len());
+    textln!();
+
+    post.revisions.iter().enumerate().for_each(|(i, v)| {
+        textln!(
+            "rev: {} | replaced: {}",
+            i + 1,
+            serde::fmt_date(&v.replaced)
+        );
+        textln!("content:");
+        textln!("{}", v.content);
+        textln!();
+    });
+
+    textln!(
+        "rev: current | updated: {:?}",
+        post.updated.as_ref().map(serde::fmt_date)
+    );
+    textln!("content:");
+    textln!("{}", post.content);
+    textln!();
+
+    output::emit(post_json(post));
+
+    None
+}
+
+fn revert_command_parse(args: types::Args) -> anyhow::Result<(schema::PostRef, usize), String> {
+    if args.len() != 2 {
+        return Err(format!(
+            "excepted 2 args, but supplied {} args.",
+            args.len()
+        ));
+    }
+
+    let num = match args[0].parse() {
+        Err(e) => return Err(format!("parse error (num): {}", e)),
+        Ok(n) => n,
+    };
+
+    let rev = match args[1].parse() {
+        Ok(n) => {
+            if n == 0 {
+                return Err("parse error (rev): cannot specify 0 or less".to_string());
+            } else {
+                n
+            }
+        }
+        Err(e) => return Err(format!("parse error (rev): {}", e)),
+    };
+
+    Ok((num, rev))
+}
+
+fn revert(args: types::Args) -> types::ExitStatus {
+    let (target, rev) = match revert_command_parse(args) {
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            None?
+        }
+        Ok(t) => t,
+    };
+
+    let _lock = lock()?;
+    let mut data = load()?;
+
+    let index = match search_post(&data, &target) {
+        Err(e) => {
+            output::error(output::Failure::NotFound, e);
+            None?
+        }
+        Ok(i) => i,
+    };
+
+    let post = data.posts.get_mut(index).unwrap();
+    let num = post.num;
+
+    let content = match post.revisions.get(rev - 1) {
+        Some(v) => v.content.clone(),
+        None => {
+            output::error(
+                output::Failure::NotFound,
+                format!(
+                    "out of range: rev {} in {}th post (has {} revisions).",
+                    rev,
+                    num,
+                    post.revisions.len()
+                ),
+            );
+            None?
+        }
+    };
+
+    let before = serde::post_to_dfsd(post.clone());
+    post.revise(content);
+    let after = serde::post_to_dfsd(post.clone());
+
+    update(post.clone())?;
+
+    record(journal::Entry::single(
+        "revert",
+        num,
+        Some(before),
+        Some(after.clone()),
+    ));
+
+    textln!("successfully revert {}th post to rev {}.", num, rev);
+    output::emit(serde_json::json!({ "post": after }));
+
+    None
 }
@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use crate::constant;
+
+lazy_static::lazy_static! {
+    /// resolved at first use, unless set by `--file` or `:open`.
+    static ref TOML_PATH: std::sync::Mutex<Option<PathBuf>> = std::sync::Mutex::new(None);
+}
+
+/// priority: `$VIRTUAL_LASAGNA_FILE` > `./posts.toml` (if exists) > `$XDG_DATA_HOME/virtual_lasagna/posts.toml`.
+/// `--file` flag overrides this by `set_toml_path`.
+pub fn default_toml_path() -> PathBuf {
+    if let Some(p) = std::env::var_os(constant::PATH_ENV) {
+        if !p.is_empty() {
+            return p.into();
+        }
+    }
+
+    let local = Path::new(constant::DEFAULT_FILE_NAME);
+    if local.exists() {
+        return local.to_path_buf();
+    }
+
+    match data_dir() {
+        Some(mut p) => {
+            p.push(constant::DATA_DIR_NAME);
+            p.push(constant::DEFAULT_FILE_NAME);
+            p
+        }
+        None => local.to_path_buf(),
+    }
+}
+
+/// `$XDG_DATA_HOME`, or `$HOME/.local/share`.
+fn data_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(p) if Path::new(&p).is_absolute() => Some(p.into()),
+        _ => std::env::var_os("HOME").filter(|v| !v.is_empty()).map(|v| {
+            [v.as_os_str(), ".local".as_ref(), "share".as_ref()]
+                .iter()
+                .collect()
+        }),
+    }
+}
+
+/// `$XDG_DATA_HOME/virtual_lasagna/history.txt`. `None` if home is unknown.
+pub fn history_path() -> Option<PathBuf> {
+    let mut p = data_dir()?;
+    p.push(constant::DATA_DIR_NAME);
+    p.push(constant::HISTORY_FILE_NAME);
+    Some(p)
+}
+
+/// absolute path with symlinks resolved, even if file itself does not exist yet.
+/// so that same file given in different forms is found. (e.g. `./posts.toml` and `posts.toml`)
+pub fn canonical_path(path: &Path) -> PathBuf {
+    if let Ok(p) = std::fs::canonicalize(path) {
+        return p;
+    }
+
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    match (std::fs::canonicalize(parent), path.file_name()) {
+        (Ok(p), Some(name)) => p.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+pub fn toml_path() -> PathBuf {
+    TOML_PATH
+        .lock()
+        .unwrap()
+        .get_or_insert_with(default_toml_path)
+        .clone()
+}
+
+pub fn set_toml_path(path: impl Into<PathBuf>) {
+    *TOML_PATH.lock().unwrap() = Some(path.into());
+}
@@ -1,25 +1,120 @@
 pub const PREFIX: &str = ":";
 pub const PREFIX_LEN: u32 = PREFIX.len() as u32;
-pub const TOML_PATH: &str = "posts.toml";
+pub const DEFAULT_FILE_NAME: &str = "posts.toml";
+pub const DATA_DIR_NAME: &str = "virtual_lasagna";
+pub const PATH_ENV: &str = "VIRTUAL_LASAGNA_FILE";
+pub const MEMORY_PATH: &str = ":memory:";
+pub const SQLITE_EXTENSIONS: [&str; 3] = ["db", "sqlite", "sqlite3"];
+pub const LOG_EXTENSION: &str = "jsonl";
+pub const FILE_FLAG: &str = "--file";
+pub const JSON_FLAG: &str = "--json";
+pub const DEFAULT_PER_PAGE: usize = 10;
+pub const JOURNAL_LIMIT: usize = 256;
+pub const BACKUP_GENERATIONS: usize = 3;
+pub const SNAPSHOT_INTERVAL: usize = 100;
+pub const HISTORY_FILE_NAME: &str = "history.txt";
+pub const HISTORY_SIZE: usize = 1000;
+/// line which finishes compose mode.
+pub const COMPOSE_TERMINATOR: &str = ".";
+/// marks [Post#num] as uuid prefix, even if it is digits only.
+pub const UUID_REF_PREFIX: &str = "u:";
+
+/// names dispatched by `commands::run`, for completion.
+pub const COMMAND_NAMES: &[&str] = &[
+    "exit", "help", "open", "format", "migrate", "repair", "export", "import", "merge", "remove",
+    "restore", "undelete", "trash", "purge", "edit", "compose", "tag", "tags", "check", "init",
+    "show", "browse", "search", "undo", "redo", "history", "revert",
+];
 
 lazy_static::lazy_static! {
     pub static ref HELP_TEXT: String = {
         format!(
             r#"help:
 
+    flags:
+        {} [path]
+            => use [path] as toml file.
+               (default: ${} > ./{} (if exists) > $XDG_DATA_HOME/{}/{})
+               "{}" keeps posts only in memory. (discarded on exit)
+               "*.db", "*.sqlite" or "*.sqlite3" is used as sqlite database.
+               "*.{}" is used as append-only log. (snapshot every {} events)
+
+        {}
+            => print one json object per command. (same as ":format json")
+
+    one-shot (run one command and exit):
+        post [Post#content: ...String]
+        show [--page: usize (default: 1)] [--per-page: usize (default: {})] [filters...]
+        edit [Post#num: u32] [Post#content: ...String]
+        remove [Post#num: u32]
+        [command] [args...]
+            => as same as below commands.
+               exit status: 0 (ok), 1 (file error), 2 (usage error), 3 (not found), 4 (conflict)
+               errors and warnings are printed to stderr. warnings do not change exit status.
+
     main:
         [Post#content: ...String]
             => post with content.
 
+    compose mode (for multi-line post):
+        {}compose
+            => post lines typed until "{}" alone.
+        [Post#content: ...String]\
+            => same as above, starting with [content]. (trailing "\" enters compose mode)
+        {}edit [Post#num: u32]
+            => edit content line by line. enter keeps the line.
+               lines after "{}" are dropped. ctrl-c or ctrl-d aborts without change.
+
+    line editing:
+        arrow keys move cursor and recall history. ctrl-r searches history.
+        tab completes command names, and [Post#num] of edit, remove, restore, tag, history and revert.
+        history is saved to $XDG_DATA_HOME/{}/{}. ctrl-d exits.
+
     commands (current prefix: "{}"):
-        check
-            => check toml file integrity.
+        (every [Post#num] also accepts uuid prefix, 4 and more hex digits. e.g. "3f2a"
+         digits are num first, and uuid prefix if no post has that num. "u:1234" is always uuid prefix)
+        check ["--fix"]
+            => check file integrity. (duplicated or missing num, max_num, timestamps)
+               "--fix" renumbers broken nums, replaces broken uuids and raises max_num, then saves.
+               other problems are only reported, and kept as they are.
+
+        repair
+            => rewrite broken toml file with every valid post.
+               broken blocks are appended to "*.quarantine.toml" next to it.
 
         init
             => initialize toml file.
 
-        show [once_show: usize] [page_num: usize]
+        show [once_show: usize] [page_num: usize] [filters...]
             => shows toml as friendly format.
+               filters:
+                   --since [date] / --until [date]: range of date. (inclusive)
+                   --date ["created" | "updated"]: date used by range. (default: created)
+                       "updated" is last edited time, or created time if never edited.
+                   --from [u32] / --to [u32]: range of num. (inclusive)
+                   --tag [String]: only posts which have the tag. (repeatable)
+                   --edited-only: only posts which have revisions.
+                   --include-deleted: also removed posts.
+                   --sort ["num" | "created" | "updated"]: order of posts. (default: num)
+                   --reverse: newest first.
+               date is "YYYY-MM-DD" (local) or rfc3339.
+               (e.g. "show 10 1 --since 2020-01-01 --date updated --reverse")
+               last page may have less than [once_show] posts.
+
+        browse [once_show: usize (default: {})] [filters...]
+            => page through posts, filtered and sorted as same as "show".
+               keys: "n" or enter (next), "p" (prev), page num (jump), "q" (quit).
+
+        tag [Post#num: u32] ["+tag" | "-tag"]...
+            => add or remove tags of [number] post.
+               hashtags (#tag) in content are also added on post and edit.
+
+        tags
+            => shows tags of not deleted posts, with post count.
+
+        search [mode: "sub" | "isub" | "regex"] [query: ...String]
+            => shows posts whose content matches query.
+               (sub: substring, isub: case-insensitive substring, regex: regular expression)
 
         edit [Post#num: u32] [Post#content: ...String]
             => edit [number] post.
@@ -27,11 +122,79 @@ lazy_static::lazy_static! {
         remove [Post#num: u32]
             => remove [number] post.
 
+        restore [Post#num: u32]
+            => restore removed [number] post. (alias: "undelete")
+
+        trash
+            => shows removed posts.
+
+        purge [days: u32 (optional)]
+            => permanently drop removed posts. (cannot undo, and clears undo history)
+               if [days] supplied, only posts removed before [days] days ago.
+               (posts whose removed time is unknown are kept)
+
+        history [Post#num: u32]
+            => shows prior revisions of [number] post.
+
+        revert [Post#num: u32] [rev: usize]
+            => restore content of [number] post from [rev] revision.
+               (current content is kept as new revision)
+
+        undo
+            => undo last post, edit, tag, remove, restore, revert, import or merge.
+               every post of one import or merge is undone at once.
+
+        redo
+            => redo last undone post, edit, tag, remove, restore or revert.
+               both fail if the post was changed after that. (e.g. by other instance)
+               "purge", "check --fix" and "repair" clear undo history.
+
+        open [path]
+            => switch toml file to [path].
+
+        export ["md" | "html" | "json"] [path] [filters...]
+            => write posts to [path] as markdown, standalone html or json array.
+               filters are same as "show". removed posts are written only with "--include-deleted".
+
+        import [path: ...String]
+            => add posts from "*.json" (array of strings or {{content, created}}),
+               "*.csv" (with "content" and optional "created" columns) or text (separated by blank lines).
+               posts get new nums. posts whose content already exists are skipped.
+
+        merge [path: ...String]
+            => merge posts of other file (any storage) into current one.
+               same post is found by its uuid, not by num. new posts are renumbered if num is used.
+               posts edited on both sides are reported as conflicts, and local ones are kept.
+
+        migrate [from: path] [to: path]
+            => copy all posts and journal from [from] to empty [to].
+               (e.g. "migrate posts.toml posts.db")
+
+        format ["text" | "json"]
+            => switch output format.
+
         help
             => show this text.
 
         exit
             => exit program."#,
-        PREFIX
+        FILE_FLAG,
+        PATH_ENV,
+        DEFAULT_FILE_NAME,
+        DATA_DIR_NAME,
+        DEFAULT_FILE_NAME,
+        MEMORY_PATH,
+        LOG_EXTENSION,
+        SNAPSHOT_INTERVAL,
+        JSON_FLAG,
+        DEFAULT_PER_PAGE,
+        PREFIX,
+        COMPOSE_TERMINATOR,
+        PREFIX,
+        COMPOSE_TERMINATOR,
+        DATA_DIR_NAME,
+        HISTORY_FILE_NAME,
+        PREFIX,
+        DEFAULT_PER_PAGE
     )};
 }
@@ -0,0 +1,169 @@
+use std::path::PathBuf;
+
+/// failure of reading or writing storage.
+/// every variant knows where it happened, so that it can be reported without panicking.
+#[derive(Debug)]
+pub enum Error {
+    /// file does not exist yet.
+    NotFound(PathBuf),
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// file is not valid toml, or does not match schema.
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// uuid of post is malformed.
+    Uuid {
+        num: u32,
+        value: String,
+        source: uuid::Error,
+    },
+    /// timestamp of post is not rfc3339.
+    Timestamp {
+        num: u32,
+        field: String,
+        value: String,
+        source: chrono::ParseError,
+    },
+    /// storage exists, but `:init` has not been done.
+    NotInitialized(PathBuf),
+    /// post to be updated does not exist.
+    MissingPost(u32),
+    /// file was written by others after last read.
+    Changed(PathBuf),
+    Sqlite {
+        path: PathBuf,
+        source: rusqlite::Error,
+    },
+    /// json in storage is broken, or could not be written. `line` is 1-based, for jsonl.
+    Json {
+        path: PathBuf,
+        line: Option<usize>,
+        source: serde_json::Error,
+    },
+    /// data could not be written as toml.
+    Serialize(toml::ser::Error),
+    /// value in sqlite meta table is broken.
+    Meta {
+        path: PathBuf,
+        key: String,
+        value: String,
+    },
+    /// event in jsonl could not be applied to preceding ones.
+    Replay {
+        path: PathBuf,
+        line: usize,
+        source: Box<Error>,
+    },
+    /// reading or writing journal failed. posts are not affected.
+    Journal(Box<Error>),
+}
+
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        let path = path.into();
+
+        if source.kind() == std::io::ErrorKind::NotFound {
+            return Error::NotFound(path);
+        }
+        Error::Io { path, source }
+    }
+
+    pub fn sqlite(path: impl Into<PathBuf>, source: rusqlite::Error) -> Self {
+        Error::Sqlite {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub fn json(path: impl Into<PathBuf>, source: serde_json::Error) -> Self {
+        Error::Json {
+            path: path.into(),
+            line: None,
+            source,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NotFound(path) => write!(
+                f,
+                r#"{} does not exist. run ":init" to create."#,
+                path.display()
+            ),
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Parse { path, source } => {
+                write!(f, "{} is broken: {}", path.display(), source)
+            }
+            Error::Uuid { num, value, source } => {
+                write!(
+                    f,
+                    "{}th post has invalid uuid: {:?} ({})",
+                    num, value, source
+                )
+            }
+            Error::Timestamp {
+                num,
+                field,
+                value,
+                source,
+            } => write!(
+                f,
+                "{}th post has invalid {}: {:?} ({})",
+                num, field, value, source
+            ),
+            Error::NotInitialized(path) => {
+                write!(f, r#"{} is not initialized. run ":init"."#, path.display())
+            }
+            Error::MissingPost(num) => write!(f, "{}th post is not found.", num),
+            Error::Changed(path) => write!(
+                f,
+                "{} was changed on disk since last read, not saved.",
+                path.display()
+            ),
+            Error::Sqlite { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Json {
+                path,
+                line: Some(line),
+                source,
+            } => write!(f, "{}:{}: {}", path.display(), line, source),
+            Error::Json {
+                path,
+                line: None,
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            Error::Serialize(source) => write!(f, "could not serialize: {}", source),
+            Error::Meta { path, key, value } => {
+                write!(f, "{}: invalid {}: {:?}", path.display(), key, value)
+            }
+            Error::Replay { path, line, source } => {
+                write!(f, "{}:{}: {}", path.display(), line, source)
+            }
+            Error::Journal(source) => write!(f, "journal: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NotFound(_) => None,
+            Error::Io { source, .. } => Some(source),
+            Error::Parse { source, .. } => Some(source),
+            Error::Uuid { source, .. } => Some(source),
+            Error::Timestamp { source, .. } => Some(source),
+            Error::NotInitialized(_) | Error::MissingPost(_) | Error::Changed(_) => None,
+            Error::Sqlite { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            Error::Serialize(source) => Some(source),
+            Error::Meta { .. } => None,
+            Error::Replay { source, .. } => Some(source.as_ref()),
+            Error::Journal(source) => Some(source.as_ref()),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{schema, serde};
+
+pub enum Format {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md" | "markdown" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            "json" => Ok(Format::Json),
+            f => Err(format!(
+                r#"unknown format: "{}" (excepted "md", "html" or "json")."#,
+                f
+            )),
+        }
+    }
+}
+
+/// for readers, not for machines. (local time, minutes)
+fn fmt_date_short(d: &crate::types::Date) -> String {
+    d.format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn markdown(user: &str, posts: &[&schema::Post]) -> String {
+    let mut out = format!("# posts of {}\n", user);
+
+    for p in posts {
+        out += format!("\n## #{} ({})\n\n", p.num, fmt_date_short(&p.created)).as_str();
+        // keeps line breaks in content.
+        out += p.content.replace('\n', "  \n").as_str();
+        out += "\n";
+        if let Some(u) = &p.updated {
+            out += format!("\n_edited at {}_\n", fmt_date_short(u)).as_str();
+        }
+    }
+
+    out
+}
+
+fn html(user: &str, posts: &[&schema::Post]) -> String {
+    let user = escape_html(user);
+    let mut out = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>posts of {0}</title>
+<style>
+body {{ max-width: 40em; margin: 2em auto; font-family: sans-serif; line-height: 1.6; }}
+article {{ border-bottom: 1px solid #ddd; padding: 1em 0; }}
+header, footer {{ color: #777; font-size: 0.9em; }}
+</style>
+</head>
+<body>
+<h1>posts of {0}</h1>
+"#,
+        user
+    );
+
+    for p in posts {
+        out += format!(
+            "<article id=\"post-{0}\">\n<header>#{0} <time datetime=\"{1}\">{2}</time></header>\n<p>{3}</p>\n",
+            p.num,
+            serde::fmt_date(&p.created),
+            fmt_date_short(&p.created),
+            escape_html(p.content.as_str()).replace('\n', "<br>\n")
+        )
+        .as_str();
+        if let Some(u) = &p.updated {
+            out += format!(
+                "<footer>edited at <time datetime=\"{}\">{}</time></footer>\n",
+                serde::fmt_date(u),
+                fmt_date_short(u)
+            )
+            .as_str();
+        }
+        out += "</article>\n";
+    }
+
+    out += "</body>\n</html>\n";
+    out
+}
+
+fn json(posts: &[&schema::Post]) -> anyhow::Result<String> {
+    let posts = posts
+        .iter()
+        .map(|p| serde::post_to_dfsd((*p).clone()))
+        .collect::<Vec<_>>();
+
+    Ok(serde_json::to_string_pretty(&posts)?)
+}
+
+/// renders `posts` as they are. (filtering and sorting is caller's work)
+pub fn render(f: &Format, user: &str, posts: &[&schema::Post]) -> anyhow::Result<String> {
+    match f {
+        Format::Markdown => Ok(markdown(user, posts)),
+        Format::Html => Ok(html(user, posts)),
+        Format::Json => json(posts),
+    }
+}
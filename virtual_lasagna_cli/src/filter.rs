@@ -0,0 +1,181 @@
+use chrono::TimeZone;
+
+use crate::{schema, types};
+
+/// which timestamp of post is used.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum DateField {
+    #[default]
+    Created,
+    /// last edited time, or created time if never edited.
+    /// not `updated` field, which is also set by remove and restore.
+    Updated,
+}
+
+impl DateField {
+    pub fn of(&self, p: &schema::Post) -> types::Date {
+        match self {
+            DateField::Created => p.created,
+            DateField::Updated => p.revisions.last().map_or(p.created, |v| v.replaced),
+        }
+    }
+}
+
+impl std::str::FromStr for DateField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(DateField::Created),
+            "updated" => Ok(DateField::Updated),
+            _ => Err(format!(
+                r#"unknown date: "{}" (excepted "created" or "updated")."#,
+                s
+            )),
+        }
+    }
+}
+
+/// range of posts, by date, num and tags.
+#[derive(Default)]
+pub struct Filter {
+    pub since: Option<types::Date>,
+    /// exclusive, unlike others. date-only value is already moved to end of the day.
+    pub until: Option<types::Date>,
+    /// which date `since` and `until` are compared with.
+    pub date: DateField,
+    pub from: Option<u32>,
+    pub to: Option<u32>,
+    /// post must have all of them.
+    pub tags: Vec<String>,
+    pub edited_only: bool,
+    pub include_deleted: bool,
+}
+
+/// order of posts. (default: by num, ascending)
+#[derive(Default)]
+pub struct Order {
+    /// `None` means num.
+    pub by: Option<DateField>,
+    pub reverse: bool,
+}
+
+/// in: "2020-01-31" (local midnight) or rfc3339.
+/// `is_end` makes exclusive end which includes `s`. (date-only value moves to end of the day)
+pub fn parse_date(s: &str, is_end: bool) -> anyhow::Result<types::Date, String> {
+    if let Ok(d) = chrono::DateTime::parse_from_rfc3339(s) {
+        let d: types::Date = d.into();
+        return Ok(if is_end {
+            d + chrono::Duration::nanoseconds(1)
+        } else {
+            d
+        });
+    }
+
+    let day = match chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        Ok(d) => d,
+        Err(e) => return Err(format!("{} (excepted YYYY-MM-DD or rfc3339)", e)),
+    };
+    let day = if is_end {
+        day.succ_opt().unwrap_or(day)
+    } else {
+        day
+    };
+
+    match chrono::Local.from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap()) {
+        chrono::LocalResult::Single(d) | chrono::LocalResult::Ambiguous(d, _) => Ok(d),
+        chrono::LocalResult::None => Err(format!("{} does not exist in local time", s)),
+    }
+}
+
+impl Filter {
+    /// in: "--edited-only" or "--include-deleted"
+    /// returns `false` if `flag` is not for filter.
+    pub fn set_flag(&mut self, flag: &str) -> bool {
+        match flag {
+            "--edited-only" => self.edited_only = true,
+            "--include-deleted" => self.include_deleted = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// in: ("--since", "2020-01-01"), ("--until", ...), ("--date", "updated"),
+    ///     ("--from", "3"), ("--to", ...), ("--tag", "idea")
+    /// returns `Ok(false)` if `key` is not for filter.
+    pub fn set(&mut self, key: &str, value: &str) -> anyhow::Result<bool, String> {
+        let num = |v: &str| {
+            v.parse::<u32>()
+                .map_err(|e| format!("parse error ({}): {}", key, e))
+        };
+        let date = |v: &str, is_end| {
+            parse_date(v, is_end).map_err(|e| format!("parse error ({}): {}", key, e))
+        };
+
+        match key {
+            "--since" => self.since = Some(date(value, false)?),
+            "--until" => self.until = Some(date(value, true)?),
+            "--date" => self.date = value.parse()?,
+            "--from" => self.from = Some(num(value)?),
+            "--to" => self.to = Some(num(value)?),
+            "--tag" => self.tags.push(schema::impls::normalize_tag(value)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    pub fn is_match(&self, p: &schema::Post) -> bool {
+        let date = self.date.of(p);
+
+        self.since.is_none_or(|d| d <= date)
+            && self.until.is_none_or(|d| date < d)
+            && self.from.is_none_or(|n| n <= p.num)
+            && self.to.is_none_or(|n| p.num <= n)
+            && self.tags.iter().all(|t| p.tags.contains(t))
+            && (!self.edited_only || !p.revisions.is_empty())
+            && (self.include_deleted || p.is_deleted != Some(true))
+    }
+}
+
+impl Order {
+    /// in: "--reverse"
+    pub fn set_flag(&mut self, flag: &str) -> bool {
+        match flag {
+            "--reverse" => self.reverse = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// in: ("--sort", "num" | "created" | "updated")
+    pub fn set(&mut self, key: &str, value: &str) -> anyhow::Result<bool, String> {
+        match (key, value) {
+            ("--sort", "num") => self.by = None,
+            ("--sort", v) => {
+                self.by = Some(v.parse().map_err(|_| {
+                    format!(
+                        r#"unknown sort: "{}" (excepted "num", "created" or "updated")."#,
+                        v
+                    )
+                })?)
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// ties are ordered by num.
+    pub fn sort<T: std::borrow::Borrow<schema::Post>>(&self, posts: &mut [T]) {
+        posts.sort_by(|a, b| {
+            let (a, b) = (a.borrow(), b.borrow());
+            let by_date = match self.by {
+                Some(f) => f.of(a).cmp(&f.of(b)),
+                None => std::cmp::Ordering::Equal,
+            };
+            by_date.then(a.num.cmp(&b.num))
+        });
+        if self.reverse {
+            posts.reverse();
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{filter, types};
+
+/// post read from other file, before numbering.
+pub struct Imported {
+    pub content: String,
+    /// `None` means "now".
+    pub created: Option<types::Date>,
+}
+
+fn parse_created(s: &str, location: impl std::fmt::Display) -> anyhow::Result<types::Date> {
+    filter::parse_date(s, false)
+        .map_err(|e| anyhow::anyhow!("{}: invalid created {:?}, {}", location, s, e))
+}
+
+/// in: `["content", ...]` or `[{"content": "...", "created": "..."}, ...]`
+/// other keys are ignored, so that exported json can be imported again.
+pub fn from_json(s: &str) -> anyhow::Result<Vec<Imported>> {
+    let v = serde_json::from_str::<serde_json::Value>(s)?;
+    let items = match v.as_array() {
+        Some(a) => a,
+        None => anyhow::bail!("json must be an array."),
+    };
+
+    let mut posts = vec![];
+    for (i, item) in items.iter().enumerate() {
+        if let Some(content) = item.as_str() {
+            posts.push(Imported {
+                content: content.to_string(),
+                created: None,
+            });
+            continue;
+        }
+
+        let content = match item.get("content").and_then(|v| v.as_str()) {
+            Some(c) => c.to_string(),
+            None => anyhow::bail!("[{}]: content is missing or not a string.", i),
+        };
+        let created = match item.get("created") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(serde_json::Value::String(c)) => {
+                Some(parse_created(c.as_str(), format!("[{}]", i))?)
+            }
+            Some(_) => anyhow::bail!("[{}]: created is not a string.", i),
+        };
+
+        posts.push(Imported { content, created });
+    }
+
+    Ok(posts)
+}
+
+/// in: csv with header row, which has `content` and optional `created` columns.
+pub fn from_csv(s: &str) -> anyhow::Result<Vec<Imported>> {
+    let mut r = csv::Reader::from_reader(s.as_bytes());
+
+    let headers = r.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|v| v.trim() == name);
+    let content_at = match column("content") {
+        Some(i) => i,
+        None => anyhow::bail!("csv header must have \"content\" column."),
+    };
+    let created_at = column("created");
+
+    let mut posts = vec![];
+    for record in r.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |p| p.line());
+
+        let content = record.get(content_at).unwrap_or("").to_string();
+        let created = match created_at.and_then(|i| record.get(i)).map(|v| v.trim()) {
+            None | Some("") => None,
+            Some(c) => Some(parse_created(c, format!("line {}", line))?),
+        };
+
+        posts.push(Imported { content, created });
+    }
+
+    Ok(posts)
+}
+
+/// in: posts separated by blank lines. surrounding spaces are trimmed.
+pub fn from_text(s: &str) -> Vec<Imported> {
+    let mut posts = vec![];
+    let mut block: Vec<&str> = vec![];
+
+    for line in s.lines().chain(std::iter::once("")) {
+        if !line.trim().is_empty() {
+            block.push(line.trim_end());
+            continue;
+        }
+        if !block.is_empty() {
+            posts.push(Imported {
+                content: block.join("\n").trim().to_string(),
+                created: None,
+            });
+            block.clear();
+        }
+    }
+
+    posts
+}
+
+/// chooses format by extension. ("*.json", "*.csv", or text)
+pub fn read(path: &std::path::Path) -> anyhow::Result<Vec<Imported>> {
+    let s = std::fs::read_to_string(path)?;
+
+    match path.extension().and_then(|v| v.to_str()) {
+        Some("json") => from_json(s.as_str()),
+        Some("csv") => from_csv(s.as_str()),
+        _ => Ok(from_text(s.as_str())),
+    }
+}
@@ -0,0 +1,137 @@
+#[allow(unused_imports)]
+use std::io::{Read, Write};
+
+use ::serde::{Deserialize, Serialize};
+
+use crate::{constant, error, schema, serde, storage};
+
+/// mutation history for `:undo` / `:redo`, stored by current storage.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Journal {
+    // empty array is a value, and toml rejects values after tables.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub undo: Vec<Entry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redo: Vec<Entry>,
+}
+
+/// one mutation, which is undone or redone as a unit. (e.g. every post of `:import`)
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(from = "StoredEntry")]
+pub struct Entry {
+    pub kind: String,
+    /// in applied order.
+    pub changes: Vec<Change>,
+}
+
+/// state of `num`th post before and after mutation.
+/// `None` means "not exists".
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Change {
+    pub num: u32,
+    pub before: Option<schema::PostForSerde>,
+    pub after: Option<schema::PostForSerde>,
+}
+
+/// entries recorded before batch was introduced have only one change, inlined.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredEntry {
+    Batch {
+        kind: String,
+        changes: Vec<Change>,
+    },
+    Single {
+        kind: String,
+        num: u32,
+        before: Option<Box<schema::PostForSerde>>,
+        after: Option<Box<schema::PostForSerde>>,
+    },
+}
+
+impl From<StoredEntry> for Entry {
+    fn from(e: StoredEntry) -> Self {
+        match e {
+            StoredEntry::Batch { kind, changes } => Entry { kind, changes },
+            StoredEntry::Single {
+                kind,
+                num,
+                before,
+                after,
+            } => Entry::single(kind, num, before.map(|v| *v), after.map(|v| *v)),
+        }
+    }
+}
+
+impl Entry {
+    pub fn single(
+        kind: impl Into<String>,
+        num: u32,
+        before: Option<schema::PostForSerde>,
+        after: Option<schema::PostForSerde>,
+    ) -> Self {
+        Entry {
+            kind: kind.into(),
+            changes: vec![Change { num, before, after }],
+        }
+    }
+
+    pub fn nums(&self) -> Vec<u32> {
+        self.changes.iter().map(|v| v.num).collect()
+    }
+}
+
+/// failures are wrapped by `Error::Journal`, to tell them from failures of posts.
+pub fn load() -> Result<Journal, error::Error> {
+    storage::with(|s| s.load_journal()).map_err(|e| error::Error::Journal(Box::new(e)))
+}
+
+pub fn save(j: &Journal) -> Result<(), error::Error> {
+    storage::with(|s| s.save_journal(j)).map_err(|e| error::Error::Journal(Box::new(e)))
+}
+
+/// for storages keeping journal as toml file. missing file is treated as empty.
+pub fn load_file(path: &std::path::Path) -> Result<Journal, error::Error> {
+    let f = std::fs::OpenOptions::new().read(true).open(path);
+
+    let mut f = match f {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Journal::default()),
+        Err(e) => return Err(error::Error::io(path, e)),
+    };
+
+    let mut buf = String::new();
+    f.read_to_string(&mut buf)
+        .map_err(|e| error::Error::io(path, e))?;
+
+    toml::de::from_str::<Journal>(buf.as_str()).map_err(|source| error::Error::Parse {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+pub fn save_file(path: &std::path::Path, j: &Journal) -> Result<(), error::Error> {
+    let s = toml::ser::to_string(j).map_err(error::Error::Serialize)?;
+
+    serde::write_atomic(path, s.as_bytes())
+}
+
+/// pushes new mutation. redo history is discarded.
+pub fn record(entry: Entry) -> Result<(), error::Error> {
+    let mut j = load()?;
+
+    j.undo.push(entry);
+    if j.undo.len() > constant::JOURNAL_LIMIT {
+        let overflowed = j.undo.len() - constant::JOURNAL_LIMIT;
+        j.undo.drain(..overflowed);
+    }
+    j.redo.clear();
+
+    save(&j)
+}
+
+/// drops every entry. used after mutations which are not recorded, and may renumber posts.
+/// (`:purge` also must not keep purged contents)
+pub fn clear() -> Result<(), error::Error> {
+    save(&Journal::default())
+}
@@ -1,24 +1,74 @@
 #![feature(try_blocks)]
 
+// `textln!` must be defined before other modules.
+#[macro_use]
+mod output;
+
+mod check;
 mod commands;
+mod config;
 mod constant;
+mod error;
+mod export;
+mod filter;
+mod import;
+mod journal;
+mod merge;
+mod repair;
+mod repl;
 mod schema;
 mod serde;
+mod storage;
 mod test;
 mod types;
 
 fn main() {
-    let stdin = std::io::stdin();
+    let rest = match parse_flags(std::env::args().skip(1)) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(output::Failure::Usage.exit_code());
+        }
+    };
 
-    let code = loop {
-        eprint!("input: ");
+    if !rest.is_empty() {
+        std::process::exit(one_shot(rest));
+    }
+
+    let mut rl = match repl::editor() {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("failed starting line editor, error: {}", e);
+            std::process::exit(output::Failure::Usage.exit_code());
+        }
+    };
 
-        let mut buf = String::new();
-        stdin.read_line(&mut buf).unwrap();
+    let code = loop {
+        let buf = match rl.readline("input: ") {
+            Ok(l) => l,
+            // ctrl-c discards current line.
+            Err(rustyline::error::ReadlineError::Interrupted) => continue,
+            // ctrl-d, or end of piped input.
+            Err(rustyline::error::ReadlineError::Eof) => break 0,
+            Err(e) => {
+                output::error(
+                    output::Failure::Usage,
+                    format!("failed reading input, error: {}", e),
+                );
+                break output::Failure::Usage.exit_code();
+            }
+        };
 
-        buf = buf.replace("\n", "").replace("\\n", "\n");
+        if !buf.trim().is_empty() {
+            if let Err(e) = repl::add_history(&mut rl, buf.as_str()) {
+                output::warn(
+                    output::Failure::Storage,
+                    format!("failed saving history, error: {}", e),
+                );
+            }
+        }
 
-        match process(buf) {
+        match process_interactive(&mut rl, buf) {
             None => continue,
             Some(code) => break code,
         }
@@ -27,6 +77,205 @@ fn main() {
     std::process::exit(code);
 }
 
+/// in: ["--file", "path"], ["--file=path"] or ["--json"], then [subcommand, args...] are returned.
+fn parse_flags(mut args: impl Iterator<Item = String>) -> anyhow::Result<Vec<String>, String> {
+    while let Some(arg) = args.next() {
+        if arg == constant::FILE_FLAG {
+            match args.next() {
+                Some(p) => config::set_toml_path(p),
+                None => return Err(format!("{} requires [path].", constant::FILE_FLAG)),
+            }
+        } else if let Some(p) = arg.strip_prefix(&format!("{}=", constant::FILE_FLAG)) {
+            config::set_toml_path(p);
+        } else if arg == constant::JSON_FLAG {
+            output::set_format(output::Format::Json);
+        } else if arg.starts_with("--") {
+            return Err(format!("unknown argument: {}", arg));
+        } else {
+            return Ok(std::iter::once(arg).chain(args).collect());
+        }
+    }
+
+    Ok(vec![])
+}
+
+/// runs one command from argv, and returns exit status.
+/// in: ["subcommand", "args"...]
+fn one_shot(mut args: Vec<String>) -> i32 {
+    let name = args.remove(0);
+
+    let translated = match name.as_str() {
+        "show" => show_subcommand_parse(args),
+        // content may be splitted by shell.
+        "post" => Ok(vec![args.join(" ")]),
+        "edit" if !args.is_empty() => {
+            let num = args.remove(0);
+            match args.join(" ") {
+                c if c.is_empty() => Err("no content supplied.".to_string()),
+                c => Ok(vec![num, c]),
+            }
+        }
+        _ => Ok(args),
+    };
+
+    let args = match translated {
+        Ok(a) => a,
+        Err(e) => {
+            output::error(output::Failure::Usage, e);
+            return output::Failure::Usage.exit_code();
+        }
+    };
+
+    let code = match name.as_str() {
+        "post" if args[0].is_empty() => {
+            output::error(output::Failure::Usage, "no content supplied.");
+            None
+        }
+        "post" => commands::post(args[0].clone()),
+        _ => commands::run(
+            name.as_str(),
+            args.iter().map(|v| v.as_str()).collect::<types::Args>(),
+        ),
+    };
+
+    match (code, output::take_failure()) {
+        (Some(c), _) if c != 0 => c,
+        (_, Some(f)) => f.exit_code(),
+        _ => 0,
+    }
+}
+
+/// in: ["--page", "2", "--per-page", "10", "--reverse", ...] (or positional args as same as ":show")
+/// flags other than paging are passed to ":show" as they are.
+fn show_subcommand_parse(mut args: Vec<String>) -> anyhow::Result<Vec<String>, String> {
+    if matches!(args.first(), Some(v) if !v.starts_with("--")) {
+        return Ok(args);
+    }
+
+    let mut page = "1".to_string();
+    let mut per_page = constant::DEFAULT_PER_PAGE.to_string();
+    let mut rest = vec![];
+
+    let mut args = args.drain(..);
+    while let Some(arg) = args.next() {
+        let (key, value) = match arg.split_once('=') {
+            Some((k, v)) => (k.to_string(), Some(v.to_string())),
+            None => (arg.clone(), None),
+        };
+
+        if key != "--page" && key != "--per-page" {
+            rest.push(arg);
+            continue;
+        }
+
+        let value = match value.or_else(|| args.next()) {
+            Some(v) => v,
+            None => return Err(format!("{} requires value.", key)),
+        };
+
+        if key == "--page" {
+            page = value;
+        } else {
+            per_page = value;
+        }
+    }
+
+    Ok(vec![per_page, page].into_iter().chain(rest).collect())
+}
+
+/// in: ":compose", ":edit [num]" or "[content]\", which need more lines. otherwise as same as `process`.
+/// what interactive line starts, before other lines are read.
+#[derive(Debug, PartialEq)]
+enum Interactive<'a> {
+    /// ":compose"
+    Compose,
+    /// ":edit [target]" without content.
+    Edit(&'a str),
+    /// post ending with "\", continued to next lines. first line is without it.
+    Continued(&'a str),
+    /// processed as it is.
+    Line,
+}
+
+fn classify_interactive(s: &str) -> Interactive<'_> {
+    let command = s
+        .strip_prefix(constant::PREFIX)
+        .map(|v| v.split(' ').filter(|v| !v.is_empty()).collect::<Vec<_>>());
+
+    match command.as_deref() {
+        Some(["compose"]) => Interactive::Compose,
+        Some(["edit", target]) => Interactive::Edit(target),
+        None if s.ends_with('\\') => Interactive::Continued(s.trim_end_matches('\\')),
+        _ => Interactive::Line,
+    }
+}
+
+fn process_interactive(rl: &mut repl::Editor, s: String) -> types::ExitStatus {
+    let s = s.replace("\\n", "\n");
+
+    match classify_interactive(s.as_str()) {
+        Interactive::Compose => {
+            let content = compose(rl, vec![], "")?;
+            process_composed(content)
+        }
+        Interactive::Edit(target) => {
+            let target = match target.parse::<schema::PostRef>() {
+                Ok(t) => t,
+                Err(e) => {
+                    output::error(output::Failure::Usage, e);
+                    None?
+                }
+            };
+            // read without lock, as composing may take long. checked again by `edit_post`.
+            let (target, current) = commands::content_of(&target)?;
+
+            let content = compose(rl, vec![], current.as_str())?;
+            if content.is_empty() {
+                output::error(output::Failure::Usage, "empty content, not edited.");
+                None?
+            }
+            if content == current {
+                textln!("content is not changed, not edited.");
+                output::emit(serde_json::json!(null));
+                None?
+            }
+            commands::edit_post(target, content, Some(current.as_str()))
+        }
+        Interactive::Continued(first) => {
+            let content = compose(rl, vec![first.to_string()], "")?;
+            process_composed(content)
+        }
+        Interactive::Line => process(s),
+    }
+}
+
+/// `None` if aborted, or failed reading input.
+fn compose(rl: &mut repl::Editor, lines: Vec<String>, initial: &str) -> Option<String> {
+    match repl::compose(rl, lines, initial) {
+        Ok(Some(c)) => Some(c),
+        Ok(None) => {
+            textln!("composing aborted.");
+            output::emit(serde_json::json!(null));
+            None
+        }
+        Err(e) => {
+            output::error(
+                output::Failure::Usage,
+                format!("failed reading input, error: {}", e),
+            );
+            None
+        }
+    }
+}
+
+/// posts composed content. empty one is not posted.
+fn process_composed(content: String) -> types::ExitStatus {
+    if content.is_empty() {
+        return commands::nop();
+    }
+    commands::post(content)
+}
+
 /// in: "[any]"
 fn process(mut s: String) -> types::ExitStatus {
     if s.is_empty() {
@@ -0,0 +1,99 @@
+use crate::schema;
+
+/// same post was changed on both sides. local one is kept.
+pub struct Conflict {
+    pub num: u32,
+    pub other_num: u32,
+    pub reason: String,
+}
+
+#[derive(Default)]
+pub struct Report {
+    /// (before, after) of every changed post. before is `None` for added one.
+    pub changes: Vec<(Option<schema::Post>, schema::Post)>,
+    /// posts which are already same on both sides.
+    pub same: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// same post across files, even if renumbered.
+fn identity(p: &schema::Post) -> uuid::Uuid {
+    p.uuid
+}
+
+/// contents from first to current.
+fn history(p: &schema::Post) -> Vec<&str> {
+    p.revisions
+        .iter()
+        .map(|v| v.content.as_str())
+        .chain(std::iter::once(p.content.as_str()))
+        .collect()
+}
+
+fn is_deleted(p: &schema::Post) -> bool {
+    p.is_deleted == Some(true)
+}
+
+/// merges `other` into `local`.
+/// posts only in `other` are added, keeping their nums if not used in `local`.
+/// edited post is taken from the side whose history contains the other's.
+pub fn merge(local: &mut schema::Schema, other: schema::Schema) -> Report {
+    let mut report = Report::default();
+
+    for theirs in other.posts {
+        let id = identity(&theirs);
+        let index = local.posts.iter().position(|v| identity(v) == id);
+
+        let index = match index {
+            Some(i) => i,
+            None => {
+                let mut post = theirs;
+                if post.num == 0 || local.posts.iter().any(|v| v.num == post.num) {
+                    post.num = local.max_num + 1;
+                }
+                if local.max_num < post.num {
+                    local.max_num = post.num;
+                }
+
+                local.posts.push(post.clone());
+                report.changes.push((None, post));
+                continue;
+            }
+        };
+
+        let ours = &mut local.posts[index];
+        let (h_ours, h_theirs) = (history(ours), history(&theirs));
+
+        let conflict = |reason: &str| Conflict {
+            num: ours.num,
+            other_num: theirs.num,
+            reason: reason.to_string(),
+        };
+
+        let is_same_deletion = is_deleted(ours) == is_deleted(&theirs);
+
+        if h_ours == h_theirs && is_same_deletion {
+            report.same += 1;
+        } else if h_ours == h_theirs {
+            report.conflicts.push(conflict("deleted on only one side."));
+        } else if !is_same_deletion {
+            report
+                .conflicts
+                .push(conflict("edited on one side, deleted on other side."));
+        } else if h_theirs.starts_with(&h_ours) {
+            let before = ours.clone();
+            *ours = schema::Post {
+                num: ours.num,
+                ..theirs
+            };
+            report.changes.push((Some(before), ours.clone()));
+        } else if h_ours.starts_with(&h_theirs) {
+            // ours is newer.
+            report.same += 1;
+        } else {
+            report.conflicts.push(conflict("edited on both sides."));
+        }
+    }
+
+    report
+}
@@ -0,0 +1,153 @@
+/// kind of command failure. used as exit status in one-shot mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    /// reading or writing file failed.
+    Storage,
+    /// unknown command or invalid args.
+    Usage,
+    /// specified post (or page, revision...) does not exist.
+    NotFound,
+    /// post is not in required state. (e.g. already deleted, nothing to undo)
+    Conflict,
+}
+
+impl Failure {
+    /// used as `error.code` in json format.
+    pub fn code(self) -> &'static str {
+        match self {
+            Failure::Storage => "storage",
+            Failure::Usage => "usage",
+            Failure::NotFound => "not_found",
+            Failure::Conflict => "conflict",
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Failure::Storage => 1,
+            Failure::Usage => 2,
+            Failure::NotFound => 3,
+            Failure::Conflict => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    /// one object per command: `{"ok": true, "data": ...}` or `{"ok": false, "error": ...}`.
+    Json,
+}
+
+lazy_static::lazy_static! {
+    static ref LAST_FAILURE: std::sync::Mutex<Option<Failure>> = std::sync::Mutex::new(None);
+    static ref FORMAT: std::sync::Mutex<Format> = std::sync::Mutex::new(Format::Text);
+    static ref WARNINGS: std::sync::Mutex<Vec<serde_json::Value>> = std::sync::Mutex::new(vec![]);
+}
+
+/// `println!` only in text format.
+/// in json format, result is printed by `emit` or `error` instead.
+macro_rules! textln {
+    ($($arg:tt)*) => {
+        if $crate::output::is_text() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub fn format() -> Format {
+    *FORMAT.lock().unwrap()
+}
+
+pub fn set_format(f: Format) {
+    *FORMAT.lock().unwrap() = f;
+}
+
+pub fn is_text() -> bool {
+    format() == Format::Text
+}
+
+/// warnings since last object are attached to it.
+fn print_json(obj: serde_json::Value) {
+    let warnings = std::mem::take(&mut *WARNINGS.lock().unwrap());
+
+    println!("{}", with_warnings(obj, warnings));
+}
+
+pub fn with_warnings(
+    mut obj: serde_json::Value,
+    warnings: Vec<serde_json::Value>,
+) -> serde_json::Value {
+    if !warnings.is_empty() {
+        obj["warnings"] = serde_json::Value::Array(warnings);
+    }
+    obj
+}
+
+pub fn error_json(kind: Failure, msg: impl std::fmt::Display) -> serde_json::Value {
+    serde_json::json!({
+        "code": kind.code(),
+        "message": msg.to_string(),
+    })
+}
+
+/// `{"ok": false, "error": {"code": ..., "message": ..., "details": ...}}`
+pub fn error_envelope(
+    kind: Failure,
+    msg: impl std::fmt::Display,
+    details: serde_json::Value,
+) -> serde_json::Value {
+    let mut e = error_json(kind, msg);
+    if !details.is_null() {
+        e["details"] = details;
+    }
+
+    serde_json::json!({
+        "ok": false,
+        "error": e,
+    })
+}
+
+/// `{"ok": true, "data": ...}`
+pub fn ok_envelope(data: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "ok": true,
+        "data": data,
+    })
+}
+
+/// prints error message (to stderr in text format), and remembers failure for exit status.
+/// command must not `emit` after this.
+pub fn error(kind: Failure, msg: impl std::fmt::Display) {
+    error_with(kind, msg, serde_json::Value::Null);
+}
+
+/// as same as `error`, with `error.details` in json format. (e.g. list of problems)
+pub fn error_with(kind: Failure, msg: impl std::fmt::Display, details: serde_json::Value) {
+    match format() {
+        Format::Text => eprintln!("{}", msg),
+        Format::Json => print_json(error_envelope(kind, msg, details)),
+    }
+    *LAST_FAILURE.lock().unwrap() = Some(kind);
+}
+
+/// as same as `error`, but command continues, and exit status is not changed.
+/// (e.g. journal failed after saving) in json format, attached to next object as `warnings`.
+pub fn warn(kind: Failure, msg: impl std::fmt::Display) {
+    match format() {
+        Format::Text => eprintln!("{}", msg),
+        Format::Json => WARNINGS.lock().unwrap().push(error_json(kind, msg)),
+    }
+}
+
+/// prints result of succeeded command in json format. (no-op in text format)
+pub fn emit(data: serde_json::Value) {
+    if format() == Format::Json {
+        print_json(ok_envelope(data));
+    }
+}
+
+/// returns failure since last call.
+pub fn take_failure() -> Option<Failure> {
+    LAST_FAILURE.lock().unwrap().take()
+}
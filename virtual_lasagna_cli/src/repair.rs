@@ -0,0 +1,193 @@
+use crate::{check, schema, serde};
+
+/// block of toml file which could not be salvaged.
+pub struct Quarantined {
+    /// 1-based line number where block starts.
+    pub line: usize,
+    pub reason: String,
+    pub text: String,
+}
+
+pub struct Repaired {
+    pub data: schema::Schema,
+    pub salvaged: usize,
+    pub quarantined: Vec<Quarantined>,
+    /// what `check::fix` did after salvaging. (e.g. duplicated nums)
+    pub fixed: Vec<String>,
+}
+
+const POSTS_HEADER: &str = "[[posts]]";
+
+/// delimiter of multi-line string still open after `line`. (`"""` or `'''`)
+/// `open` is one still open before it.
+fn open_multiline(line: &str, mut open: Option<&'static str>) -> Option<&'static str> {
+    // delimiters are ascii, so that bytes are scanned without caring char boundary.
+    let bytes = line.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+
+        match open {
+            Some(d) if rest.starts_with(d.as_bytes()) => {
+                // up to 2 more quotes are part of content. (e.g. `"""""`)
+                let run = bytes[i..].iter().take_while(|&&b| b == bytes[i]).count();
+                i += run.min(5);
+                open = None;
+            }
+            Some("\"\"\"") if bytes[i] == b'\\' => i += 2,
+            Some(_) => i += 1,
+            None if rest.starts_with(b"\"\"\"") => {
+                open = Some("\"\"\"");
+                i += 3;
+            }
+            None if rest.starts_with(b"'''") => {
+                open = Some("'''");
+                i += 3;
+            }
+            None => match bytes[i] {
+                b'"' => {
+                    i += 1;
+                    while i < bytes.len() && bytes[i] != b'"' {
+                        i += if bytes[i] == b'\\' { 2 } else { 1 };
+                    }
+                    i += 1;
+                }
+                b'\'' => {
+                    i += 1;
+                    while i < bytes.len() && bytes[i] != b'\'' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                b'#' => break,
+                _ => i += 1,
+            },
+        }
+    }
+
+    open
+}
+
+/// splits toml text into header and `[[posts]]` blocks. (with their first line numbers)
+/// `[[posts.revisions]]` stays in its post block, and so does `[[posts]]` in multi-line string.
+fn split_blocks(text: &str) -> Vec<(usize, String)> {
+    let mut blocks = vec![(1, String::new())];
+    let mut open = None;
+
+    for (i, line) in text.lines().enumerate() {
+        if open.is_none() && line.trim() == POSTS_HEADER {
+            blocks.push((i + 1, String::new()));
+        }
+        open = open_multiline(line, open);
+
+        let (_, block) = blocks.last_mut().unwrap();
+        block.push_str(line);
+        block.push('\n');
+    }
+
+    blocks
+}
+
+fn parse_header(text: &str) -> anyhow::Result<(String, Option<u32>)> {
+    let v = toml::from_str::<toml::Value>(text)?;
+
+    let user = match v.get("user").and_then(|v| v.as_str()) {
+        Some(u) => u.to_string(),
+        None => anyhow::bail!("user is missing or not a string."),
+    };
+    let max_num = v
+        .get("max_num")
+        .and_then(|v| v.as_integer())
+        .map(|v| v.clamp(0, u32::MAX as i64) as u32);
+
+    Ok((user, max_num))
+}
+
+fn parse_post(text: &str) -> anyhow::Result<schema::Post> {
+    let v = toml::from_str::<toml::Value>(text)?;
+
+    let post = match v
+        .get("posts")
+        .and_then(|v| v.as_array())
+        .map(|v| v.as_slice())
+    {
+        Some([p]) => p.clone(),
+        _ => anyhow::bail!("block does not contain exactly one post."),
+    };
+    let post = post.try_into::<schema::PostForSerde>()?;
+
+    Ok(serde::post_from_dfsd(post)?)
+}
+
+/// parses toml text post by post, keeping every valid post.
+/// posts are renumbered by `check::fix` if their nums are duplicated.
+pub fn repair(text: &str) -> anyhow::Result<Repaired> {
+    let mut blocks = split_blocks(text).into_iter();
+    let mut quarantined = vec![];
+
+    let (line, header) = blocks.next().unwrap();
+    let (user, max_num) = match parse_header(header.as_str()) {
+        Ok(h) => h,
+        Err(e) => {
+            quarantined.push(Quarantined {
+                line,
+                reason: e.to_string(),
+                text: header,
+            });
+            (String::new(), None)
+        }
+    };
+
+    let mut posts = vec![];
+    for (line, text) in blocks {
+        match parse_post(text.as_str()) {
+            Ok(p) => posts.push(p),
+            Err(e) => quarantined.push(Quarantined {
+                line,
+                reason: e.to_string(),
+                text,
+            }),
+        }
+    }
+    let salvaged = posts.len();
+
+    let mut raw = toml::Value::try_from(serde::convert_to_dfsd(schema::Schema {
+        user,
+        max_num: max_num.unwrap_or(0),
+        posts,
+    }))?;
+    let fixed = check::fix(&mut raw);
+    let data = serde::convert_from_dfsd(raw.try_into::<schema::SchemaForSerde>()?)?;
+
+    Ok(Repaired {
+        data,
+        salvaged,
+        quarantined,
+        fixed,
+    })
+}
+
+/// appends quarantined blocks to sidecar file, keeping earlier ones.
+pub fn quarantine(path: &std::path::Path, blocks: &[Quarantined]) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let mut f = std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)?;
+
+    let now = serde::fmt_date(&chrono::Local::now());
+    for b in blocks {
+        writeln!(
+            f,
+            "# quarantined at {} from line {}: {}",
+            now, b.line, b.reason
+        )?;
+        f.write_all(b.text.as_bytes())?;
+        writeln!(f)?;
+    }
+    f.sync_all()?;
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+
+use crate::{config, constant, storage};
+
+pub type Editor = rustyline::Editor<ReplHelper, rustyline::history::FileHistory>;
+
+/// commands whose first arg is num of not deleted post.
+const POST_COMMANDS: &[&str] = &["edit", "remove", "tag", "history", "revert"];
+/// commands whose first arg is num of removed post.
+const REMOVED_POST_COMMANDS: &[&str] = &["restore", "undelete"];
+
+/// completes command names after prefix, and post nums as their first arg.
+pub struct ReplHelper;
+
+/// first line of content, cut to fit in completion list.
+fn preview(content: &str) -> String {
+    let line = content.lines().next().unwrap_or("");
+    match line.char_indices().nth(40) {
+        Some((i, _)) => format!("{}...", &line[..i]),
+        None => line.to_string(),
+    }
+}
+
+fn command_candidates(typed: &str) -> Vec<Pair> {
+    constant::COMMAND_NAMES
+        .iter()
+        .filter(|v| v.starts_with(typed))
+        .map(|v| Pair {
+            display: v.to_string(),
+            replacement: format!("{} ", v),
+        })
+        .collect()
+}
+
+fn post_candidates(name: &str, typed: &str) -> Vec<Pair> {
+    let removed = if POST_COMMANDS.contains(&name) {
+        false
+    } else if REMOVED_POST_COMMANDS.contains(&name) {
+        true
+    } else {
+        return vec![];
+    };
+
+    // file may not exist yet. nothing to complete then.
+    let data = match storage::with(|s| s.load()) {
+        Ok(d) => d,
+        Err(_) => return vec![],
+    };
+
+    let mut posts = data
+        .posts
+        .iter()
+        .filter(|v| (v.is_deleted == Some(true)) == removed)
+        .filter(|v| v.num.to_string().starts_with(typed))
+        .collect::<Vec<_>>();
+    posts.sort_by_key(|v| v.num);
+
+    posts
+        .iter()
+        .map(|v| Pair {
+            display: format!("{}: {}", v.num, preview(v.content.as_str())),
+            replacement: format!("{} ", v.num),
+        })
+        .collect()
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    /// in: ":ed" => command names, ":edit 1" => nums. plain posts are not completed.
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let rest = match line.strip_prefix(constant::PREFIX) {
+            Some(r) => r,
+            None => return Ok((pos, vec![])),
+        };
+
+        match rest.split(' ').collect::<Vec<_>>().as_slice() {
+            [name] => Ok((pos - name.len(), command_candidates(name))),
+            [name, typed] => Ok((pos - typed.len(), post_candidates(name, typed))),
+            _ => Ok((pos, vec![])),
+        }
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl rustyline::Helper for ReplHelper {}
+
+/// line editor with emacs keys (ctrl-r searches history), and history of last session.
+pub fn editor() -> rustyline::Result<Editor> {
+    let config = rustyline::Config::builder()
+        .max_history_size(constant::HISTORY_SIZE)?
+        .history_ignore_dups(true)?
+        .completion_type(rustyline::CompletionType::List)
+        .build();
+
+    let mut rl = Editor::with_config(config)?;
+    rl.set_helper(Some(ReplHelper));
+
+    // no history yet at first run.
+    if let Some(p) = config::history_path() {
+        let _ = rl.load_history(&p);
+    }
+
+    Ok(rl)
+}
+
+/// reads lines until terminator, and returns them joined. `None` if aborted by ctrl-c or ctrl-d.
+/// each line of `initial` is offered to be edited, before empty lines.
+pub fn compose(
+    rl: &mut Editor,
+    mut lines: Vec<String>,
+    initial: &str,
+) -> rustyline::Result<Option<String>> {
+    eprintln!(
+        r#"composing... "{}" alone to finish, ctrl-c to abort."#,
+        constant::COMPOSE_TERMINATOR
+    );
+
+    let mut initial = initial.lines();
+    loop {
+        let line = match initial.next() {
+            Some(l) => rl.readline_with_initial("... ", (l, "")),
+            None => rl.readline("... "),
+        };
+
+        match line {
+            Ok(l) if l == constant::COMPOSE_TERMINATOR => break,
+            Ok(l) => lines.push(l),
+            Err(rustyline::error::ReadlineError::Interrupted)
+            | Err(rustyline::error::ReadlineError::Eof) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(Some(join_lines(lines.as_slice())))
+}
+
+/// content of composed lines. trailing empty lines are dropped.
+pub fn join_lines(lines: &[String]) -> String {
+    lines.join("\n").trim_end().to_string()
+}
+
+/// saved on every line, so that history survives killed process.
+pub fn add_history(rl: &mut Editor, line: &str) -> anyhow::Result<()> {
+    rl.add_history_entry(line)?;
+
+    let path = match config::history_path() {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    rl.save_history(&path)?;
+
+    Ok(())
+}
@@ -1,13 +1,113 @@
-use crate::schema;
+use std::collections::BTreeSet;
+
+use crate::{constant, schema};
+
+lazy_static::lazy_static! {
+    /// "#" at start of word, followed by letters, digits, "_" or "-".
+    static ref HASHTAG: regex::Regex = regex::Regex::new(r"(?:^|\s)#([\w-]+)").unwrap();
+}
+
+/// tags written as "#tag" in content.
+pub fn hashtags(content: &str) -> BTreeSet<String> {
+    HASHTAG
+        .captures_iter(content)
+        .map(|c| c[1].to_lowercase())
+        .collect()
+}
+
+/// in: "foo" or "#foo". out: "foo"
+pub fn normalize_tag(s: &str) -> anyhow::Result<String, String> {
+    let tag = s.strip_prefix('#').unwrap_or(s).to_lowercase();
+    if tag.is_empty()
+        || !tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(format!(
+            "{:?} is not valid tag (letters, digits, \"_\" or \"-\").",
+            s
+        ));
+    }
+    Ok(tag)
+}
 
 impl schema::Post {
     pub fn new(content: impl ToString, num: u32) -> Self {
+        let content = content.to_string();
+
         Self {
-            content: content.to_string(),
+            tags: hashtags(content.as_str()),
+            content,
             num,
+            uuid: uuid::Uuid::new_v4(),
             created: chrono::offset::Local::now(),
             updated: None,
             is_deleted: None,
+            revisions: vec![],
+        }
+    }
+
+    /// replaces content, keeping current one as revision.
+    /// hashtags removed from content are untagged, but tags added by `:tag` are kept.
+    pub fn revise(&mut self, content: impl ToString) {
+        let now = chrono::offset::Local::now();
+
+        let old = std::mem::replace(&mut self.content, content.to_string());
+        for t in hashtags(old.as_str()) {
+            self.tags.remove(&t);
+        }
+        self.tags.extend(hashtags(self.content.as_str()));
+
+        self.revisions.push(schema::Revision {
+            content: old,
+            replaced: now,
+        });
+        self.updated = Some(now);
+    }
+}
+
+impl std::str::FromStr for schema::PostRef {
+    type Err = String;
+
+    /// digits are num, unless they are 4 and more with leading zero. uuid prefix needs 4 chars at least.
+    /// "u:" forces uuid prefix, for prefix of digits only. ("u:1234")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let explicit = s.strip_prefix(constant::UUID_REF_PREFIX);
+
+        if explicit.is_none() && !(s.len() >= 4 && s.starts_with('0')) {
+            if let Ok(n) = s.parse() {
+                return Ok(schema::PostRef::Num(n));
+            }
+        }
+
+        let prefix = explicit.unwrap_or(s).replace('-', "").to_lowercase();
+        if prefix.len() < 4 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!(
+                "{:?} is neither num nor uuid prefix (4 and more hex digits).",
+                s
+            ));
+        }
+        Ok(schema::PostRef::Uuid(prefix))
+    }
+}
+
+impl schema::PostRef {
+    /// num which may be uuid prefix as well. used when no post has that num.
+    pub fn uuid_fallback(&self) -> Option<schema::PostRef> {
+        match self {
+            schema::PostRef::Num(n) if n.to_string().len() >= 4 => {
+                Some(schema::PostRef::Uuid(n.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_match(&self, p: &schema::Post) -> bool {
+        match self {
+            schema::PostRef::Num(n) => p.num == *n,
+            schema::PostRef::Uuid(prefix) => {
+                p.uuid.simple().to_string().starts_with(prefix.as_str())
+            }
         }
     }
 }
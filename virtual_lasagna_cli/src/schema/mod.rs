@@ -1,7 +1,8 @@
 pub mod impls;
 
+use std::collections::BTreeSet;
+
 use serde::{Deserialize, Serialize};
-use smallvec::SmallVec;
 
 use crate::types;
 
@@ -9,29 +10,63 @@ use crate::types;
 pub struct SchemaForSerde {
     pub user: String,
     pub max_num: u32,
-    pub posts: SmallVec<[PostForSerde; 1024]>,
+    pub posts: Vec<PostForSerde>,
 }
 
+#[derive(Clone)]
 pub struct Schema {
     pub user: String,
     pub max_num: u32,
-    pub posts: smallvec::SmallVec<[Post; 1024]>,
+    pub posts: Vec<Post>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Post {
     pub num: u32,
+    /// unique across files, unlike num.
+    pub uuid: uuid::Uuid,
     pub content: String,
     pub created: types::Date,
     pub updated: Option<types::Date>,
     pub is_deleted: Option<bool>,
+    pub revisions: Vec<Revision>,
+    /// lowercase, without "#". filled from hashtags in content, or by `:tag`.
+    pub tags: BTreeSet<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PostForSerde {
     pub num: u32,
+    /// missing in files written before uuid was introduced.
+    #[serde(default)]
+    pub uuid: Option<String>,
     pub content: String,
     pub created: String,
     pub updated: Option<String>,
     pub is_deleted: Option<bool>,
+    /// before revisions, since toml needs values before tables.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    pub revisions: Option<Vec<RevisionForSerde>>,
+}
+
+/// prior content of post, pushed by edit.
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub content: String,
+    pub replaced: types::Date,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RevisionForSerde {
+    pub content: String,
+    pub replaced: String,
+}
+
+/// how commands address a post: `num`, or prefix of uuid. ("4", "3f2a", "3f2a9c1e-...")
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostRef {
+    Num(u32),
+    /// lowercase, without hyphens.
+    Uuid(String),
 }
@@ -1,44 +1,235 @@
 #[allow(unused_imports)]
 use std::io::{Read, Write};
 
-use crate::{constant, schema};
+use crate::{constant, error, schema};
 
-fn open_toml_file(truncate: bool) -> std::fs::File {
-    let mut oo = &mut std::fs::OpenOptions::new();
-    oo = oo.read(true).write(true);
-    if truncate {
-        oo = oo.truncate(true);
+fn open_toml_file(path: &std::path::Path) -> Result<std::fs::File, error::Error> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .open(path)
+        .map_err(|e| error::Error::io(path, e))
+}
+
+fn path_with_suffix(path: &std::path::Path, suffix: &str) -> std::path::PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(suffix);
+    s.into()
+}
+
+/// writes to temporary file in same directory, then renames it over `path`.
+/// `path` is never left half-written, even if process dies.
+/// temporary file is unique, so that writers without lock do not collide. (e.g. snapshot)
+pub fn write_atomic(path: &std::path::Path, bytes: &[u8]) -> Result<(), error::Error> {
+    static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    let n = COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    let tmp = path_with_suffix(path, format!(".{}.{}.tmp", std::process::id(), n).as_str());
+
+    let mut f = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp)
+        .map_err(|e| error::Error::io(&tmp, e))?;
+    let r = f
+        .write_all(bytes)
+        .and_then(|_| f.sync_all())
+        .map_err(|e| error::Error::io(&tmp, e))
+        .and_then(|_| std::fs::rename(&tmp, path).map_err(|e| error::Error::io(path, e)));
+    drop(f);
+    if r.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    r?;
+
+    // rename itself is persisted by syncing parent directory.
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => std::path::Path::new("."),
+        };
+        std::fs::File::open(dir)
+            .and_then(|v| v.sync_all())
+            .map_err(|e| error::Error::io(dir, e))?;
+    }
+
+    Ok(())
+}
+
+/// copies current `path` to `{path}.bak`, shifting older ones to `{path}.bak.1`, `{path}.bak.2`...
+fn rotate_backup(path: &std::path::Path) -> Result<(), error::Error> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let bak = |n: usize| {
+        if n == 0 {
+            path_with_suffix(path, ".bak")
+        } else {
+            path_with_suffix(path, format!(".bak.{}", n).as_str())
+        }
+    };
+
+    for n in (1..constant::BACKUP_GENERATIONS).rev() {
+        let from = bak(n - 1);
+        if from.exists() {
+            std::fs::rename(&from, bak(n)).map_err(|e| error::Error::io(&from, e))?;
+        }
+    }
+
+    std::fs::copy(path, bak(0)).map_err(|e| error::Error::io(bak(0), e))?;
+
+    Ok(())
+}
+
+/// same format as stored in toml file (rfc3339 with nanoseconds).
+pub fn fmt_date(d: &crate::types::Date) -> String {
+    d.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)
+}
+
+pub fn post_to_dfsd(p: schema::Post) -> schema::PostForSerde {
+    let schema::Post {
+        num,
+        uuid,
+        content,
+        created,
+        updated,
+        is_deleted,
+        mut revisions,
+        tags,
+    } = p;
+
+    let revisions = revisions
+        .drain(..)
+        .map(|v| schema::RevisionForSerde {
+            content: v.content,
+            replaced: fmt_date(&v.replaced),
+        })
+        .collect::<Vec<_>>();
+
+    schema::PostForSerde {
+        num,
+        uuid: Some(uuid.to_string()),
+        content,
+        created: fmt_date(&created),
+        updated: updated.as_ref().map(fmt_date),
+        is_deleted,
+        revisions: if revisions.is_empty() {
+            None
+        } else {
+            Some(revisions)
+        },
+        tags: if tags.is_empty() {
+            None
+        } else {
+            Some(tags.into_iter().collect())
+        },
     }
+}
+
+fn parse_date(
+    num: u32,
+    field: impl ToString,
+    value: &str,
+) -> Result<crate::types::Date, error::Error> {
+    match chrono::prelude::DateTime::<chrono::FixedOffset>::parse_from_rfc3339(value) {
+        Ok(d) => Ok(d.into()),
+        Err(source) => Err(error::Error::Timestamp {
+            num,
+            field: field.to_string(),
+            value: value.to_string(),
+            source,
+        }),
+    }
+}
+
+/// uuid for post written before uuid was introduced.
+/// derived from created time, num and content, so that it is same on every load and every copy of the file.
+/// (num is unique in a file, so posts created at same time get different ones)
+/// version 8, because it is not random.
+pub fn legacy_uuid(created: &crate::types::Date, num: u32, content: &str) -> uuid::Uuid {
+    // FNV-1a, 128 bit. DefaultHasher may change across builds.
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    let nanos =
+        created.timestamp() as i128 * 1_000_000_000 + created.timestamp_subsec_nanos() as i128;
 
-    oo.open(constant::TOML_PATH).unwrap()
+    let h = nanos
+        .to_le_bytes()
+        .iter()
+        .chain(num.to_le_bytes().iter())
+        .chain(content.as_bytes())
+        .fold(OFFSET, |h, b| (h ^ *b as u128).wrapping_mul(PRIME));
+
+    uuid::Uuid::new_v8(h.to_be_bytes())
 }
 
-fn convert_to_dfsd(s: schema::Schema) -> schema::SchemaForSerde {
+pub fn post_from_dfsd(p: schema::PostForSerde) -> Result<schema::Post, error::Error> {
+    let schema::PostForSerde {
+        num,
+        uuid,
+        content,
+        created,
+        updated,
+        is_deleted,
+        revisions,
+        tags,
+    } = p;
+
+    let created = parse_date(num, "created", created.as_str())?;
+    let uuid = match uuid {
+        Some(v) => match uuid::Uuid::parse_str(v.as_str()) {
+            Ok(u) => u,
+            Err(source) => {
+                return Err(error::Error::Uuid {
+                    num,
+                    value: v,
+                    source,
+                })
+            }
+        },
+        None => legacy_uuid(&created, num, content.as_str()),
+    };
+
+    Ok(schema::Post {
+        num,
+        uuid,
+        content,
+        created,
+        updated: match updated {
+            Some(v) => Some(parse_date(num, "updated", v.as_str())?),
+            None => None,
+        },
+        is_deleted,
+        revisions: revisions
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| {
+                Ok(schema::Revision {
+                    replaced: parse_date(
+                        num,
+                        format!("revisions[{}].replaced", i),
+                        v.replaced.as_str(),
+                    )?,
+                    content: v.content,
+                })
+            })
+            .collect::<Result<_, error::Error>>()?,
+        tags: tags.unwrap_or_default().into_iter().collect(),
+    })
+}
+
+pub fn convert_to_dfsd(s: schema::Schema) -> schema::SchemaForSerde {
     let schema::Schema {
         user,
         max_num,
         mut posts,
     } = s;
 
-    let posts = posts
-        .drain(..)
-        .map(|v| {
-            let schema::Post {
-                num,
-                content,
-                created,
-                updated,
-                is_deleted,
-            } = v;
-            schema::PostForSerde {
-                num,
-                content,
-                created: created.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
-                updated: updated.map(|v| v.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)),
-                is_deleted,
-            }
-        })
-        .collect::<smallvec::SmallVec<_>>();
+    let posts = posts.drain(..).map(post_to_dfsd).collect::<Vec<_>>();
 
     schema::SchemaForSerde {
         user,
@@ -47,7 +238,7 @@ fn convert_to_dfsd(s: schema::Schema) -> schema::SchemaForSerde {
     }
 }
 
-fn convert_from_dfsd(s: schema::SchemaForSerde) -> schema::Schema {
+pub fn convert_from_dfsd(s: schema::SchemaForSerde) -> Result<schema::Schema, error::Error> {
     let schema::SchemaForSerde {
         user,
         max_num,
@@ -56,60 +247,153 @@ fn convert_from_dfsd(s: schema::SchemaForSerde) -> schema::Schema {
 
     let posts = posts
         .drain(..)
-        .map(|v| {
-            let schema::PostForSerde {
-                num,
-                content,
-                created,
-                updated,
-                is_deleted,
-            } = v;
-            schema::Post {
-                num,
-                content,
-                created: chrono::prelude::DateTime::<chrono::FixedOffset>::parse_from_rfc3339(
-                    created.as_str(),
-                )
-                .unwrap()
-                .into(),
-                updated: updated.map(|v| {
-                    chrono::prelude::DateTime::<chrono::FixedOffset>::parse_from_rfc3339(v.as_str())
-                        .unwrap()
-                        .into()
-                }),
-                is_deleted,
-            }
-        })
-        .collect::<smallvec::SmallVec<_>>();
+        .map(post_from_dfsd)
+        .collect::<Result<Vec<_>, _>>()?;
 
-    schema::Schema {
+    Ok(schema::Schema {
         user,
         max_num,
         posts,
-    }
+    })
 }
 
-pub fn de() -> schema::Schema {
-    let r = de_inner();
+pub fn de(path: &std::path::Path) -> Result<schema::Schema, error::Error> {
+    let r = de_inner(path)?;
 
-    convert_from_dfsd(r.unwrap())
+    convert_from_dfsd(r)
 }
 
-pub fn de_inner() -> anyhow::Result<schema::SchemaForSerde> {
-    let mut f = open_toml_file(false);
+pub fn de_inner(path: &std::path::Path) -> Result<schema::SchemaForSerde, error::Error> {
+    let mut f = open_toml_file(path)?;
     let mut buf = String::new();
-    f.read_to_string(&mut buf)?;
+    f.read_to_string(&mut buf)
+        .map_err(|e| error::Error::io(path, e))?;
+
+    remember_read(path, buf.as_bytes());
+
+    toml::de::from_str::<schema::SchemaForSerde>(buf.as_str()).map_err(|source| {
+        error::Error::Parse {
+            path: path.to_path_buf(),
+            source,
+        }
+    })
+}
+
+/// toml file as raw value, without schema validation.
+pub fn de_value(path: &std::path::Path) -> Result<toml::Value, error::Error> {
+    let mut f = open_toml_file(path)?;
+    let mut buf = String::new();
+    f.read_to_string(&mut buf)
+        .map_err(|e| error::Error::io(path, e))?;
+
+    remember_read(path, buf.as_bytes());
+
+    toml::de::from_str::<toml::Value>(buf.as_str()).map_err(|source| error::Error::Parse {
+        path: path.to_path_buf(),
+        source,
+    })
+}
 
-    Ok(toml::de::from_str::<schema::SchemaForSerde>(buf.as_str())?)
+pub fn ser(path: &std::path::Path, data: schema::Schema) -> Result<(), error::Error> {
+    let data = convert_to_dfsd(data);
+    let s = toml::ser::to_string(&data).map_err(error::Error::Serialize)?;
+
+    write_toml(path, s)
+}
+
+/// raw value as it is. (e.g. fixed by `:check --fix`, but other problems remain)
+pub fn ser_value(path: &std::path::Path, data: &toml::Value) -> Result<(), error::Error> {
+    let s = toml::ser::to_string(data).map_err(error::Error::Serialize)?;
+
+    write_toml(path, s)
 }
 
-pub fn ser(data: schema::Schema) {
-    let r: anyhow::Result<()> = try {
-        let data = convert_to_dfsd(data);
-        let s = toml::ser::to_string(&data)?;
-        let mut f = open_toml_file(true);
-        f.write_all(s.as_bytes())?;
+fn write_toml(path: &std::path::Path, s: String) -> Result<(), error::Error> {
+    verify_unchanged(path)?;
+    rotate_backup(path)?;
+    write_atomic(path, s.as_bytes())?;
+
+    remember_read(path, s.as_bytes());
+
+    Ok(())
+}
+
+lazy_static::lazy_static! {
+    /// hash of toml file content at last `de_inner` in current lock, by path.
+    /// (other files may be read while `:merge` or `:migrate`)
+    static ref LAST_READ: std::sync::Mutex<std::collections::HashMap<std::path::PathBuf, u64>> =
+        std::sync::Mutex::new(std::collections::HashMap::new());
+}
+
+fn remember_read(path: &std::path::Path, bytes: &[u8]) {
+    LAST_READ
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), hash_of(bytes));
+}
+
+/// not stable across builds. only for comparing in one process.
+pub fn hash_of(bytes: &[u8]) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    bytes.hash(&mut h);
+    h.finish()
+}
+
+/// fails if toml file was changed by others after last read.
+fn verify_unchanged(path: &std::path::Path) -> Result<(), error::Error> {
+    let expected = match LAST_READ.lock().unwrap().get(path) {
+        Some(h) => *h,
+        None => return Ok(()),
+    };
+
+    let current = match std::fs::read(path) {
+        Ok(b) => Some(hash_of(b.as_slice())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(error::Error::io(path, e)),
     };
 
-    r.unwrap();
+    if current != Some(expected) {
+        return Err(error::Error::Changed(path.to_path_buf()));
+    }
+
+    Ok(())
+}
+
+/// exclusive advisory lock for `{path}.lock`, released on drop.
+pub struct Lock(std::fs::File);
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs2::FileExt::unlock(&self.0);
+    }
+}
+
+/// blocks until other instances release the lock.
+pub fn lock(path: &std::path::Path) -> Result<Lock, error::Error> {
+    let lock_path = path_with_suffix(path, ".lock");
+
+    // default path (in data dir) may not be created yet.
+    if let Some(dir) = lock_path.parent().filter(|v| !v.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| error::Error::io(dir, e))?;
+    }
+
+    let f = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|e| error::Error::io(&lock_path, e))?;
+
+    if fs2::FileExt::try_lock_exclusive(&f).is_err() {
+        textln!("waiting for other instance to release lock...");
+        fs2::FileExt::lock_exclusive(&f).map_err(|e| error::Error::io(&lock_path, e))?;
+    }
+
+    // data read before this lock may be outdated.
+    LAST_READ.lock().unwrap().remove(path);
+
+    Ok(Lock(f))
 }
@@ -0,0 +1,474 @@
+use std::io::{BufRead, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use ::serde::{Deserialize, Serialize};
+
+use crate::{check, constant, error, journal, output, schema, serde, storage};
+
+/// one line of log.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event {
+    /// whole schema is replaced. (`:init`, `:purge`, `:undo`...)
+    Reset {
+        user: String,
+        max_num: u32,
+        posts: Vec<schema::PostForSerde>,
+    },
+    Append {
+        post: schema::PostForSerde,
+    },
+    Update {
+        post: schema::PostForSerde,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Line {
+    at: String,
+    #[serde(flatten)]
+    event: Event,
+}
+
+/// state after first `lines` lines of log.
+/// `bytes` and `hash` are of those lines, so that snapshot of replaced log is not used.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    lines: usize,
+    bytes: u64,
+    hash: u64,
+    user: String,
+    max_num: u32,
+    posts: Vec<schema::PostForSerde>,
+}
+
+/// append-only log of events, one json object per line.
+/// schema is rebuilt by replaying log from latest snapshot. ("posts.jsonl" -> "posts.snapshot.json")
+/// journal is kept next to it as same as toml. ("posts.jsonl" -> "posts.journal.toml")
+pub struct JsonlStorage {
+    path: PathBuf,
+}
+
+impl JsonlStorage {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    fn snapshot_path(&self) -> PathBuf {
+        self.path.with_extension("snapshot.json")
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.path.with_extension("journal.toml")
+    }
+
+    /// snapshot which still matches head of `log`.
+    /// snapshot is only cache, log is the truth. so broken or outdated one is dropped.
+    fn load_snapshot(&self, log: &mut std::fs::File) -> Result<Option<Snapshot>, error::Error> {
+        let path = self.snapshot_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let f = std::fs::File::open(&path).map_err(|e| error::Error::io(&path, e))?;
+        let s = match serde_json::from_reader::<_, Snapshot>(std::io::BufReader::new(f)) {
+            Ok(s) => s,
+            Err(_) => {
+                self.remove_snapshot();
+                return Ok(None);
+            }
+        };
+
+        let mut head = vec![];
+        log.take(s.bytes)
+            .read_to_end(&mut head)
+            .map_err(|e| error::Error::io(&self.path, e))?;
+
+        if head.len() as u64 != s.bytes || fnv1a(FNV_OFFSET, head.as_slice()) != s.hash {
+            self.remove_snapshot();
+            log.rewind().map_err(|e| error::Error::io(&self.path, e))?;
+            return Ok(None);
+        }
+
+        Ok(Some(s))
+    }
+
+    /// failure is only warned, as snapshot is rebuilt at next load.
+    fn save_snapshot(&self, s: &Snapshot) {
+        let path = self.snapshot_path();
+        let r = serde_json::to_string(s)
+            .map_err(|e| error::Error::json(&path, e))
+            .and_then(|s| serde::write_atomic(&path, s.as_bytes()));
+
+        if let Err(e) = r {
+            output::warn(
+                output::Failure::Storage,
+                format!("failed saving snapshot, error: {}", e),
+            );
+        }
+    }
+
+    /// not found, or failed to remove, is ignored. outdated one is dropped at load anyway.
+    fn remove_snapshot(&self) {
+        let _ = std::fs::remove_file(self.snapshot_path());
+    }
+
+    fn push(&mut self, event: Event) -> Result<(), error::Error> {
+        let line = serde_json::to_string(&Line {
+            at: serde::fmt_date(&chrono::Local::now()),
+            event,
+        })
+        .map_err(|e| error::Error::json(&self.path, e))?;
+
+        self.push_line(line + "\n")
+    }
+
+    fn push_line(&mut self, line: String) -> Result<(), error::Error> {
+        let r: std::io::Result<()> = try {
+            let mut f = std::fs::OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(&self.path)?;
+
+            truncate_torn_line(&mut f)?;
+            f.write_all(line.as_bytes())?;
+            f.sync_data()?;
+        };
+        r.map_err(|e| error::Error::io(&self.path, e))
+    }
+}
+
+/// drops last line torn by crash, so that new line is not glued to it.
+/// complete last line without newline (e.g. edited by hand) is kept, and newline is added.
+/// only tail of file is read when it ends with newline.
+fn truncate_torn_line(f: &mut std::fs::File) -> std::io::Result<()> {
+    let len = f.metadata()?.len();
+    let mut buf = [0u8; 4096];
+    let mut end = len;
+    let mut keep = 0;
+
+    while end > 0 {
+        let start = end.saturating_sub(buf.len() as u64);
+        let chunk = &mut buf[..(end - start) as usize];
+        f.seek(std::io::SeekFrom::Start(start))?;
+        f.read_exact(chunk)?;
+
+        if let Some(i) = chunk.iter().rposition(|&b| b == b'\n') {
+            keep = start + i as u64 + 1;
+            break;
+        }
+        end = start;
+    }
+
+    if keep == len {
+        return Ok(());
+    }
+
+    let mut tail = vec![];
+    f.seek(std::io::SeekFrom::Start(keep))?;
+    f.read_to_end(&mut tail)?;
+
+    if serde_json::from_slice::<Line>(tail.as_slice()).is_ok() {
+        f.write_all(b"\n")?;
+    } else {
+        f.set_len(keep)?;
+    }
+    Ok(())
+}
+
+/// state replayed without validation. broken lines are skipped and reported.
+struct RawState {
+    user: Option<serde_json::Value>,
+    max_num: Option<serde_json::Value>,
+    posts: Vec<serde_json::Value>,
+    unreadable: Vec<check::Problem>,
+}
+
+impl RawState {
+    fn apply(&mut self, line: usize, v: serde_json::Value) {
+        if let Err(message) = self.try_apply(v) {
+            self.unreadable.push(check::Problem {
+                location: format!("line {}", line),
+                message,
+                fixable: false,
+            });
+        }
+    }
+
+    fn try_apply(&mut self, mut v: serde_json::Value) -> Result<(), String> {
+        match v.get("event").and_then(|v| v.as_str()) {
+            Some("reset") => match v["posts"].take() {
+                serde_json::Value::Array(posts) => {
+                    self.user = Some(v["user"].take());
+                    self.max_num = Some(v["max_num"].take());
+                    self.posts = posts;
+                }
+                _ => return Err("reset without posts array.".to_string()),
+            },
+            Some("append") => {
+                let post = v["post"].take();
+                let num = post.get("num").and_then(|v| v.as_u64());
+                let max_num = self.max_num.as_ref().and_then(|v| v.as_u64());
+                if let (Some(n), Some(m)) = (num, max_num) {
+                    if m < n {
+                        self.max_num = Some(n.into());
+                    }
+                }
+                self.posts.push(post);
+            }
+            Some("update") => {
+                let post = v["post"].take();
+                match self
+                    .posts
+                    .iter_mut()
+                    .find(|v| v.get("num") == post.get("num"))
+                {
+                    Some(p) => *p = post,
+                    None => return Err(format!("updated post {} is not found.", post["num"])),
+                }
+            }
+            _ => return Err("unknown event.".to_string()),
+        }
+        Ok(())
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// FNV-1a, 64 bit. continues from `h`, so that hash of log grows line by line.
+/// (DefaultHasher may change across builds, but snapshot is kept in file)
+fn fnv1a(h: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(h, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+fn apply(s: &mut Snapshot, event: Event) -> Result<(), error::Error> {
+    match event {
+        Event::Reset {
+            user,
+            max_num,
+            posts,
+        } => {
+            s.user = user;
+            s.max_num = max_num;
+            s.posts = posts;
+        }
+        Event::Append { post } => {
+            if s.max_num < post.num {
+                s.max_num = post.num;
+            }
+            s.posts.push(post);
+        }
+        Event::Update { post } => match s.posts.iter_mut().find(|v| v.num == post.num) {
+            Some(v) => *v = post,
+            None => return Err(error::Error::MissingPost(post.num)),
+        },
+    }
+    Ok(())
+}
+
+impl storage::Storage for JsonlStorage {
+    fn lock(&mut self) -> Result<Option<serde::Lock>, error::Error> {
+        Ok(Some(serde::lock(&self.path)?))
+    }
+
+    fn load(&mut self) -> Result<schema::Schema, error::Error> {
+        if !self.path.exists() {
+            return Err(error::Error::NotFound(self.path.clone()));
+        }
+
+        let mut f = std::fs::File::open(&self.path).map_err(|e| error::Error::io(&self.path, e))?;
+
+        // log is read on from where snapshot ends.
+        let mut state = match self.load_snapshot(&mut f)? {
+            Some(s) => s,
+            None => Snapshot {
+                lines: 0,
+                bytes: 0,
+                hash: FNV_OFFSET,
+                user: String::new(),
+                max_num: 0,
+                posts: vec![],
+            },
+        };
+        let mut is_initialized = state.lines > 0;
+
+        let mut reader = std::io::BufReader::new(f);
+        let mut line = String::new();
+        let mut replayed = 0;
+
+        loop {
+            line.clear();
+            let read: std::io::Result<bool> = try {
+                reader.read_line(&mut line)? != 0 && {
+                    // last line may be torn by crash.
+                    let is_last = reader.fill_buf()?.is_empty();
+                    !is_last || serde_json::from_str::<Line>(line.trim_end()).is_ok()
+                }
+            };
+            if !read.map_err(|e| error::Error::io(&self.path, e))? {
+                break;
+            }
+            let i = state.lines;
+
+            state.lines += 1;
+            state.bytes += line.len() as u64;
+            state.hash = fnv1a(state.hash, line.as_bytes());
+
+            let text = line.trim_end_matches(&['\n', '\r'][..]);
+            if text.is_empty() {
+                continue;
+            }
+
+            let event = serde_json::from_str::<Line>(text)
+                .map_err(|source| error::Error::Json {
+                    path: self.path.clone(),
+                    line: Some(i + 1),
+                    source,
+                })?
+                .event;
+
+            if let Event::Reset { .. } = event {
+                is_initialized = true;
+            }
+            apply(&mut state, event).map_err(|e| error::Error::Replay {
+                path: self.path.clone(),
+                line: i + 1,
+                source: Box::new(e),
+            })?;
+
+            replayed += 1;
+        }
+
+        if !is_initialized {
+            return Err(error::Error::NotInitialized(self.path.clone()));
+        }
+
+        if replayed >= constant::SNAPSHOT_INTERVAL {
+            self.save_snapshot(&state);
+        }
+
+        Ok(schema::Schema {
+            user: state.user,
+            max_num: state.max_num,
+            posts: state
+                .posts
+                .into_iter()
+                .map(serde::post_from_dfsd)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// replays whole log without snapshot, so that every broken line is reported.
+    fn load_raw(&mut self) -> Result<storage::Raw, error::Error> {
+        if !self.path.exists() {
+            return Err(error::Error::NotFound(self.path.clone()));
+        }
+
+        let mut state = RawState {
+            user: None,
+            max_num: None,
+            posts: vec![],
+            unreadable: vec![],
+        };
+        let mut is_initialized = false;
+
+        let f = std::fs::File::open(&self.path).map_err(|e| error::Error::io(&self.path, e))?;
+        let mut lines = std::io::BufReader::new(f).lines().enumerate().peekable();
+
+        while let Some((i, line)) = lines.next() {
+            let line = line.map_err(|e| error::Error::io(&self.path, e))?;
+            if line.is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str::<serde_json::Value>(line.as_str()) {
+                Ok(v) => {
+                    is_initialized |= v.get("event").and_then(|v| v.as_str()) == Some("reset");
+                    state.apply(i + 1, v);
+                }
+                // last line may be torn by crash.
+                Err(_) if lines.peek().is_none() => break,
+                Err(e) => state.unreadable.push(check::Problem {
+                    location: format!("line {}", i + 1),
+                    message: format!("is not valid json: {}", e),
+                    fixable: false,
+                }),
+            }
+        }
+
+        if !is_initialized {
+            return Err(error::Error::NotInitialized(self.path.clone()));
+        }
+
+        let data = serde_json::json!({
+            "user": state.user,
+            "max_num": state.max_num,
+            "posts": state.posts,
+        });
+        Ok(storage::Raw {
+            data: storage::json_to_toml(data).unwrap_or_else(|| toml::value::Table::new().into()),
+            unreadable: state.unreadable,
+        })
+    }
+
+    fn save(&mut self, data: schema::Schema) -> Result<(), error::Error> {
+        let schema::Schema {
+            user,
+            max_num,
+            posts,
+        } = data;
+
+        self.push(Event::Reset {
+            user,
+            max_num,
+            posts: posts.into_iter().map(serde::post_to_dfsd).collect(),
+        })?;
+
+        // it covers nothing after reset.
+        self.remove_snapshot();
+        Ok(())
+    }
+
+    /// as reset event, which replaces everything before.
+    fn save_raw(&mut self, data: &toml::Value) -> Result<(), error::Error> {
+        let mut line = serde_json::to_value(data).map_err(|e| error::Error::json(&self.path, e))?;
+        if let Some(o) = line.as_object_mut() {
+            o.insert(
+                "at".to_string(),
+                serde::fmt_date(&chrono::Local::now()).into(),
+            );
+            o.insert("event".to_string(), "reset".into());
+        }
+
+        let line = serde_json::to_string(&line).map_err(|e| error::Error::json(&self.path, e))?;
+        self.push_line(line + "\n")?;
+
+        self.remove_snapshot();
+        Ok(())
+    }
+
+    fn append(&mut self, post: schema::Post) -> Result<(), error::Error> {
+        self.push(Event::Append {
+            post: serde::post_to_dfsd(post),
+        })
+    }
+
+    fn update(&mut self, post: schema::Post) -> Result<(), error::Error> {
+        self.push(Event::Update {
+            post: serde::post_to_dfsd(post),
+        })
+    }
+
+    fn load_journal(&mut self) -> Result<journal::Journal, error::Error> {
+        journal::load_file(&self.journal_path())
+    }
+
+    fn save_journal(&mut self, j: &journal::Journal) -> Result<(), error::Error> {
+        journal::save_file(&self.journal_path(), j)
+    }
+}
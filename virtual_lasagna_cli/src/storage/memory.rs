@@ -0,0 +1,102 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{constant, error, journal, schema, serde, storage};
+
+struct State {
+    data: schema::Schema,
+    journal: journal::Journal,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            data: schema::Schema {
+                user: String::new(),
+                max_num: 0,
+                posts: vec![],
+            },
+            journal: journal::Journal::default(),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// behind ":memory:". every storage opened for it sees same posts.
+    static ref SHARED: Arc<Mutex<State>> = Arc::new(Mutex::new(State::default()));
+}
+
+/// keeps everything in memory. nothing survives process exit.
+/// `default()` is empty and private, `shared()` is same in whole process.
+#[derive(Default)]
+pub struct MemoryStorage {
+    state: Arc<Mutex<State>>,
+}
+
+impl MemoryStorage {
+    pub fn shared() -> Self {
+        Self {
+            state: SHARED.clone(),
+        }
+    }
+}
+
+impl storage::Storage for MemoryStorage {
+    fn lock(&mut self) -> Result<Option<serde::Lock>, error::Error> {
+        Ok(None)
+    }
+
+    fn load(&mut self) -> Result<schema::Schema, error::Error> {
+        Ok(self.state.lock().unwrap().data.clone())
+    }
+
+    fn max_num(&mut self) -> Result<u32, error::Error> {
+        Ok(self.state.lock().unwrap().data.max_num)
+    }
+
+    fn save(&mut self, data: schema::Schema) -> Result<(), error::Error> {
+        self.state.lock().unwrap().data = data;
+        Ok(())
+    }
+
+    /// only valid data can be kept.
+    fn save_raw(&mut self, data: &toml::Value) -> Result<(), error::Error> {
+        let data = data
+            .clone()
+            .try_into::<schema::SchemaForSerde>()
+            .map_err(|source| error::Error::Parse {
+                path: constant::MEMORY_PATH.into(),
+                source,
+            })?;
+
+        self.save(serde::convert_from_dfsd(data)?)
+    }
+
+    fn append(&mut self, post: schema::Post) -> Result<(), error::Error> {
+        let data = &mut self.state.lock().unwrap().data;
+
+        if data.max_num < post.num {
+            data.max_num = post.num;
+        }
+        data.posts.push(post);
+        Ok(())
+    }
+
+    fn update(&mut self, post: schema::Post) -> Result<(), error::Error> {
+        let data = &mut self.state.lock().unwrap().data;
+
+        match data.posts.iter_mut().find(|v| v.num == post.num) {
+            Some(v) => *v = post,
+            None => return Err(error::Error::MissingPost(post.num)),
+        }
+        Ok(())
+    }
+
+    fn load_journal(&mut self) -> Result<journal::Journal, error::Error> {
+        Ok(self.state.lock().unwrap().journal.clone())
+    }
+
+    fn save_journal(&mut self, j: &journal::Journal) -> Result<(), error::Error> {
+        self.state.lock().unwrap().journal = j.clone();
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+pub mod jsonl;
+pub mod memory;
+pub mod sqlite;
+pub mod toml_file;
+
+use std::path::{Path, PathBuf};
+
+use crate::{check, config, constant, error, journal, schema, serde};
+
+/// as stored, before validation. used by `:check`.
+pub struct Raw {
+    pub data: toml::Value,
+    /// records which could not be read into `data` at all. (e.g. broken line of jsonl)
+    pub unreadable: Vec<check::Problem>,
+}
+
+/// where posts (and journal) are persisted.
+/// `append` and `update` fall back to `load` + `save`, override them if storage can do better.
+pub trait Storage {
+    /// held while read-modify-write. `None` if storage needs no lock.
+    fn lock(&mut self) -> Result<Option<serde::Lock>, error::Error>;
+
+    fn load(&mut self) -> Result<schema::Schema, error::Error>;
+
+    /// same as `load()?.max_num`, without reading every post if storage can.
+    fn max_num(&mut self) -> Result<u32, error::Error> {
+        Ok(self.load()?.max_num)
+    }
+
+    /// every readable record is kept, even if other ones are broken.
+    fn load_raw(&mut self) -> Result<Raw, error::Error> {
+        let data = serde::convert_to_dfsd(self.load()?);
+
+        Ok(Raw {
+            data: toml::Value::try_from(data).map_err(error::Error::Serialize)?,
+            unreadable: vec![],
+        })
+    }
+
+    /// overwrites everything with `load_raw` result fixed by `:check --fix`.
+    /// problems which could not be fixed are kept as they are, if storage can hold them.
+    fn save_raw(&mut self, data: &toml::Value) -> Result<(), error::Error>;
+
+    /// overwrites everything.
+    fn save(&mut self, data: schema::Schema) -> Result<(), error::Error>;
+
+    /// adds new post. `max_num` follows its num.
+    fn append(&mut self, post: schema::Post) -> Result<(), error::Error> {
+        let mut data = self.load()?;
+
+        if data.max_num < post.num {
+            data.max_num = post.num;
+        }
+        data.posts.push(post);
+
+        self.save(data)
+    }
+
+    /// replaces post which has same num.
+    fn update(&mut self, post: schema::Post) -> Result<(), error::Error> {
+        let mut data = self.load()?;
+
+        match data.posts.iter_mut().find(|v| v.num == post.num) {
+            Some(v) => *v = post,
+            None => return Err(error::Error::MissingPost(post.num)),
+        }
+
+        self.save(data)
+    }
+
+    fn load_journal(&mut self) -> Result<journal::Journal, error::Error>;
+
+    fn save_journal(&mut self, j: &journal::Journal) -> Result<(), error::Error>;
+}
+
+/// `null` is dropped, as toml has no null. (e.g. missing optional field)
+pub fn json_to_toml(v: serde_json::Value) -> Option<toml::Value> {
+    use serde_json::Value;
+
+    Some(match v {
+        Value::Null => return None,
+        Value::Bool(b) => toml::Value::Boolean(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => toml::Value::Float(n.as_f64()?),
+        },
+        Value::String(s) => toml::Value::String(s),
+        Value::Array(a) => toml::Value::Array(a.into_iter().filter_map(json_to_toml).collect()),
+        Value::Object(o) => toml::Value::Table(
+            o.into_iter()
+                .filter_map(|(k, v)| Some((k, json_to_toml(v)?)))
+                .collect(),
+        ),
+    })
+}
+
+/// chooses storage by path. (":memory:", "*.db" / "*.sqlite" / "*.sqlite3", "*.jsonl", or toml)
+pub fn open(path: &Path) -> Box<dyn Storage + Send> {
+    if path.as_os_str() == constant::MEMORY_PATH {
+        return Box::new(memory::MemoryStorage::shared());
+    }
+
+    let ext = path.extension().and_then(|v| v.to_str()).unwrap_or("");
+    if constant::SQLITE_EXTENSIONS.contains(&ext) {
+        return Box::new(sqlite::SqliteStorage::new(path));
+    }
+    if ext == constant::LOG_EXTENSION {
+        return Box::new(jsonl::JsonlStorage::new(path));
+    }
+
+    Box::new(toml_file::TomlStorage::new(path))
+}
+
+/// true if `open` chooses toml file for `path`.
+pub fn is_toml(path: &Path) -> bool {
+    let ext = path.extension().and_then(|v| v.to_str()).unwrap_or("");
+
+    path.as_os_str() != constant::MEMORY_PATH
+        && !constant::SQLITE_EXTENSIONS.contains(&ext)
+        && ext != constant::LOG_EXTENSION
+}
+
+lazy_static::lazy_static! {
+    /// reopened when `config::toml_path()` is changed.
+    static ref CURRENT: std::sync::Mutex<Option<(PathBuf, Box<dyn Storage + Send>)>> =
+        std::sync::Mutex::new(None);
+}
+
+/// runs `f` with storage for current path.
+/// storage is taken out while `f` runs, so that `with` can be called inside `f`.
+/// (nested call opens its own storage for same path)
+pub fn with<T>(f: impl FnOnce(&mut dyn Storage) -> T) -> T {
+    let path = config::toml_path();
+
+    let taken = match CURRENT.lock().unwrap().take() {
+        Some((p, s)) if p == path => Some(s),
+        // stale one is dropped.
+        _ => None,
+    };
+    let mut s = taken.unwrap_or_else(|| open(&path));
+
+    let r = f(s.as_mut());
+
+    let mut current = CURRENT.lock().unwrap();
+    if current.is_none() {
+        *current = Some((path, s));
+    }
+    r
+}
@@ -0,0 +1,438 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, OptionalExtension};
+
+use crate::{error, journal, schema, serde, storage};
+
+const CREATE_TABLES: &str = r#"
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS posts (
+    num INTEGER PRIMARY KEY,
+    uuid TEXT,
+    content TEXT NOT NULL,
+    created TEXT NOT NULL,
+    updated TEXT,
+    is_deleted INTEGER,
+    revisions TEXT,
+    tags TEXT
+);
+CREATE INDEX IF NOT EXISTS posts_created ON posts (created);
+CREATE INDEX IF NOT EXISTS posts_uuid ON posts (uuid);
+CREATE INDEX IF NOT EXISTS posts_is_deleted ON posts (is_deleted);
+"#;
+
+/// posts in sqlite database. (bundled, no server)
+/// posts are appended / updated by row, not rewriting whole file.
+/// revisions, tags and journal are kept as json text.
+pub struct SqliteStorage {
+    path: PathBuf,
+    conn: Option<rusqlite::Connection>,
+}
+
+impl SqliteStorage {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            conn: None,
+        }
+    }
+
+    /// connects at first use, creating tables if needed.
+    fn conn(&mut self) -> Result<&mut rusqlite::Connection, error::Error> {
+        if self.conn.is_none() {
+            let conn = open(&self.path).map_err(|e| error::Error::sqlite(&self.path, e))?;
+            self.conn = Some(conn);
+        }
+
+        Ok(self.conn.as_mut().unwrap())
+    }
+
+    fn get_meta(&mut self, key: &str) -> Result<Option<String>, error::Error> {
+        let path = self.path.clone();
+
+        self.conn()?
+            .query_row("SELECT value FROM meta WHERE key = ?1", params![key], |r| {
+                r.get(0)
+            })
+            .optional()
+            .map_err(|e| error::Error::sqlite(path, e))
+    }
+
+    /// fails if not initialized yet.
+    fn user(&mut self) -> Result<String, error::Error> {
+        if !self.path.exists() {
+            return Err(error::Error::NotFound(self.path.clone()));
+        }
+
+        match self.get_meta("user")? {
+            Some(u) => Ok(u),
+            None => Err(error::Error::NotInitialized(self.path.clone())),
+        }
+    }
+
+    /// runs `f` in transaction. rolled back if `f` fails.
+    fn transaction(
+        &mut self,
+        f: impl FnOnce(&rusqlite::Transaction) -> rusqlite::Result<()>,
+    ) -> Result<(), error::Error> {
+        let path = self.path.clone();
+        let conn = self.conn()?;
+
+        let r: rusqlite::Result<()> = try {
+            let tx = conn.transaction()?;
+            f(&tx)?;
+            tx.commit()?;
+        };
+        r.map_err(|e| error::Error::sqlite(path, e))
+    }
+}
+
+fn open(path: &Path) -> rusqlite::Result<rusqlite::Connection> {
+    let conn = rusqlite::Connection::open(path)?;
+
+    // databases created before uuid or tags was introduced. (before indexes on them are created)
+    // no columns if posts table does not exist yet.
+    let columns = conn
+        .prepare("SELECT name FROM pragma_table_info('posts')")?
+        .query_map([], |r| r.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if !columns.is_empty() {
+        for column in ["uuid", "tags"] {
+            if !columns.iter().any(|v| v == column) {
+                let alter = format!("ALTER TABLE posts ADD COLUMN {} TEXT", column);
+                conn.execute(alter.as_str(), [])?;
+            }
+        }
+    }
+    conn.execute_batch(CREATE_TABLES)?;
+
+    Ok(conn)
+}
+
+fn set_meta(conn: &rusqlite::Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO meta (key, value) VALUES (?1, ?2)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+/// json text of column. failure is reported as conversion failure of sqlite.
+fn to_json_column<T: ::serde::Serialize>(v: &Option<T>) -> rusqlite::Result<Option<String>> {
+    match v {
+        Some(v) => serde_json::to_string(v)
+            .map(Some)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e))),
+        None => Ok(None),
+    }
+}
+
+/// inserts, or replaces row which has same num.
+fn put_post(conn: &rusqlite::Connection, post: schema::Post) -> rusqlite::Result<()> {
+    let p = serde::post_to_dfsd(post);
+    let revisions = to_json_column(&p.revisions)?;
+    let tags = to_json_column(&p.tags)?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO posts (num, uuid, content, created, updated, is_deleted, revisions, tags)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            p.num,
+            p.uuid,
+            p.content,
+            p.created,
+            p.updated,
+            p.is_deleted,
+            revisions,
+            tags
+        ],
+    )?;
+    Ok(())
+}
+
+/// keeps max_num >= `num`.
+fn follow_max_num(conn: &rusqlite::Connection, num: u32) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE meta SET value = ?1 WHERE key = 'max_num' AND CAST(value AS INTEGER) < ?1",
+        params![num],
+    )?;
+    Ok(())
+}
+
+type Row = (schema::PostForSerde, Option<String>, Option<String>);
+
+fn select_posts(conn: &rusqlite::Connection) -> rusqlite::Result<Vec<Row>> {
+    let mut stmt = conn.prepare(
+        "SELECT num, uuid, content, created, updated, is_deleted, revisions, tags
+         FROM posts ORDER BY num",
+    )?;
+    let rows = stmt.query_map([], |r| {
+        Ok((
+            schema::PostForSerde {
+                num: r.get(0)?,
+                uuid: r.get(1)?,
+                content: r.get(2)?,
+                created: r.get(3)?,
+                updated: r.get(4)?,
+                is_deleted: r.get(5)?,
+                tags: None,
+                revisions: None,
+            },
+            r.get::<_, Option<String>>(6)?,
+            r.get::<_, Option<String>>(7)?,
+        ))
+    })?;
+
+    rows.collect()
+}
+
+const POST_COLUMNS: [&str; 8] = [
+    "num",
+    "uuid",
+    "content",
+    "created",
+    "updated",
+    "is_deleted",
+    "revisions",
+    "tags",
+];
+
+/// every column as it is, except is_deleted as boolean. json columns are parsed if valid,
+/// otherwise kept as text. (so that `:check` reports them, and `save_raw` writes them back)
+fn raw_column(name: &str, v: rusqlite::types::Value) -> Option<toml::Value> {
+    use rusqlite::types::Value;
+
+    Some(match (name, v) {
+        (_, Value::Null) => return None,
+        ("is_deleted", Value::Integer(n)) => toml::Value::Boolean(n != 0),
+        ("revisions" | "tags", Value::Text(s)) => {
+            match serde_json::from_str(s.as_str())
+                .ok()
+                .and_then(storage::json_to_toml)
+            {
+                Some(v) => v,
+                None => toml::Value::String(s),
+            }
+        }
+        (_, Value::Integer(n)) => toml::Value::Integer(n),
+        (_, Value::Real(f)) => toml::Value::Float(f),
+        (_, Value::Text(s)) => toml::Value::String(s),
+        (_, Value::Blob(b)) => toml::Value::String(String::from_utf8_lossy(&b).into_owned()),
+    })
+}
+
+/// reverse of `raw_column`.
+fn sql_column(v: Option<&toml::Value>) -> rusqlite::Result<rusqlite::types::Value> {
+    use rusqlite::types::Value;
+
+    Ok(match v {
+        None => Value::Null,
+        Some(toml::Value::Integer(n)) => Value::Integer(*n),
+        Some(toml::Value::Float(f)) => Value::Real(*f),
+        Some(toml::Value::Boolean(b)) => Value::Integer(*b as i64),
+        Some(toml::Value::String(s)) => Value::Text(s.clone()),
+        Some(v) => Value::Text(
+            serde_json::to_string(v)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
+        ),
+    })
+}
+
+fn select_raw(conn: &rusqlite::Connection) -> rusqlite::Result<toml::Value> {
+    let mut data = toml::value::Table::new();
+
+    let mut stmt = conn.prepare("SELECT key, value FROM meta WHERE key IN ('user', 'max_num')")?;
+    let meta = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?;
+    for row in meta {
+        let (key, value) = row?;
+        let value = match value.parse() {
+            Ok(n) if key == "max_num" => toml::Value::Integer(n),
+            _ => toml::Value::String(value),
+        };
+        data.insert(key, value);
+    }
+
+    let mut stmt = conn
+        .prepare(format!("SELECT {} FROM posts ORDER BY num", POST_COLUMNS.join(", ")).as_str())?;
+    let rows = stmt.query_map([], |r| {
+        let mut post = toml::value::Table::new();
+        for (i, name) in POST_COLUMNS.iter().enumerate() {
+            if let Some(v) = raw_column(name, r.get(i)?) {
+                post.insert(name.to_string(), v);
+            }
+        }
+        Ok(toml::Value::Table(post))
+    })?;
+    data.insert(
+        "posts".to_string(),
+        toml::Value::Array(rows.collect::<rusqlite::Result<_>>()?),
+    );
+
+    Ok(toml::Value::Table(data))
+}
+
+impl storage::Storage for SqliteStorage {
+    fn lock(&mut self) -> Result<Option<serde::Lock>, error::Error> {
+        Ok(Some(serde::lock(&self.path)?))
+    }
+
+    fn load(&mut self) -> Result<schema::Schema, error::Error> {
+        let user = self.user()?;
+        let max_num = self.max_num()?;
+
+        let path = self.path.clone();
+        let rows = select_posts(self.conn()?).map_err(|e| error::Error::sqlite(&path, e))?;
+
+        let mut posts = vec![];
+        for (mut p, revisions, tags) in rows {
+            if let Some(r) = revisions {
+                p.revisions = Some(
+                    serde_json::from_str(r.as_str()).map_err(|e| error::Error::json(&path, e))?,
+                );
+            }
+            if let Some(t) = tags {
+                p.tags = Some(
+                    serde_json::from_str(t.as_str()).map_err(|e| error::Error::json(&path, e))?,
+                );
+            }
+            posts.push(serde::post_from_dfsd(p)?);
+        }
+
+        Ok(schema::Schema {
+            user,
+            max_num,
+            posts,
+        })
+    }
+
+    /// broken column does not hide other posts.
+    fn load_raw(&mut self) -> Result<storage::Raw, error::Error> {
+        if !self.path.exists() {
+            return Err(error::Error::NotFound(self.path.clone()));
+        }
+
+        let path = self.path.clone();
+        let data = select_raw(self.conn()?).map_err(|e| error::Error::sqlite(path, e))?;
+
+        Ok(storage::Raw {
+            data,
+            unreadable: vec![],
+        })
+    }
+
+    /// only meta table is read.
+    fn max_num(&mut self) -> Result<u32, error::Error> {
+        self.user()?;
+
+        match self.get_meta("max_num")? {
+            Some(n) => n.parse().map_err(|_| error::Error::Meta {
+                path: self.path.clone(),
+                key: "max_num".to_string(),
+                value: n,
+            }),
+            None => Ok(0),
+        }
+    }
+
+    fn save(&mut self, data: schema::Schema) -> Result<(), error::Error> {
+        let schema::Schema {
+            user,
+            max_num,
+            posts,
+        } = data;
+
+        self.transaction(|tx| {
+            tx.execute("DELETE FROM posts", [])?;
+            for p in posts {
+                put_post(tx, p)?;
+            }
+            set_meta(tx, "user", user.as_str())?;
+            set_meta(tx, "max_num", max_num.to_string().as_str())
+        })
+    }
+
+    fn save_raw(&mut self, data: &toml::Value) -> Result<(), error::Error> {
+        let posts = data
+            .get("posts")
+            .and_then(|v| v.as_array())
+            .map(|v| v.as_slice())
+            .unwrap_or(&[]);
+        let insert = format!(
+            "INSERT INTO posts ({}) VALUES ({})",
+            POST_COLUMNS.join(", "),
+            (1..=POST_COLUMNS.len())
+                .map(|i| format!("?{}", i))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        self.transaction(|tx| {
+            tx.execute("DELETE FROM posts", [])?;
+            for p in posts {
+                let columns = POST_COLUMNS
+                    .iter()
+                    .map(|name| sql_column(p.get(name)))
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                tx.execute(insert.as_str(), rusqlite::params_from_iter(columns))?;
+            }
+
+            if let Some(u) = data.get("user").and_then(|v| v.as_str()) {
+                set_meta(tx, "user", u)?;
+            }
+            if let Some(n) = data.get("max_num").and_then(|v| v.as_integer()) {
+                set_meta(tx, "max_num", n.to_string().as_str())?;
+            }
+            Ok(())
+        })
+    }
+
+    fn append(&mut self, post: schema::Post) -> Result<(), error::Error> {
+        self.transaction(|tx| {
+            let num = post.num;
+            put_post(tx, post)?;
+            follow_max_num(tx, num)
+        })
+    }
+
+    fn update(&mut self, post: schema::Post) -> Result<(), error::Error> {
+        let num = post.num;
+        let mut exists = false;
+
+        self.transaction(|tx| {
+            exists = tx
+                .query_row("SELECT 1 FROM posts WHERE num = ?1", params![num], |_| {
+                    Ok(())
+                })
+                .optional()?
+                .is_some();
+            if exists {
+                put_post(tx, post)?;
+            }
+            Ok(())
+        })?;
+
+        if !exists {
+            return Err(error::Error::MissingPost(num));
+        }
+        Ok(())
+    }
+
+    fn load_journal(&mut self) -> Result<journal::Journal, error::Error> {
+        match self.get_meta("journal")? {
+            Some(j) => {
+                serde_json::from_str(j.as_str()).map_err(|e| error::Error::json(&self.path, e))
+            }
+            None => Ok(journal::Journal::default()),
+        }
+    }
+
+    fn save_journal(&mut self, j: &journal::Journal) -> Result<(), error::Error> {
+        let s = serde_json::to_string(j).map_err(|e| error::Error::json(&self.path, e))?;
+        self.transaction(|tx| set_meta(tx, "journal", s.as_str()))
+    }
+}
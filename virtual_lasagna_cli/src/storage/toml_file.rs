@@ -0,0 +1,54 @@
+use std::path::{Path, PathBuf};
+
+use crate::{error, journal, schema, serde, storage};
+
+/// whole schema in one toml file, rewritten on every save.
+/// journal is kept next to it. ("posts.toml" -> "posts.journal.toml")
+pub struct TomlStorage {
+    path: PathBuf,
+}
+
+impl TomlStorage {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.path.with_extension("journal.toml")
+    }
+}
+
+impl storage::Storage for TomlStorage {
+    fn lock(&mut self) -> Result<Option<serde::Lock>, error::Error> {
+        Ok(Some(serde::lock(&self.path)?))
+    }
+
+    fn load(&mut self) -> Result<schema::Schema, error::Error> {
+        serde::de(&self.path)
+    }
+
+    fn load_raw(&mut self) -> Result<storage::Raw, error::Error> {
+        Ok(storage::Raw {
+            data: serde::de_value(&self.path)?,
+            unreadable: vec![],
+        })
+    }
+
+    fn save(&mut self, data: schema::Schema) -> Result<(), error::Error> {
+        serde::ser(&self.path, data)
+    }
+
+    fn save_raw(&mut self, data: &toml::Value) -> Result<(), error::Error> {
+        serde::ser_value(&self.path, data)
+    }
+
+    fn load_journal(&mut self) -> Result<journal::Journal, error::Error> {
+        journal::load_file(&self.journal_path())
+    }
+
+    fn save_journal(&mut self, j: &journal::Journal) -> Result<(), error::Error> {
+        journal::save_file(&self.journal_path(), j)
+    }
+}
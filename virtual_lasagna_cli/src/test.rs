@@ -300,3 +300,1472 @@ fn file_io_test() {
         assert_eq!(buf, astr.to_owned() + bstr);
     }
 }
+
+/// unique path in temp dir for each call. file is not created.
+#[cfg(test)]
+pub fn temp_path(ext: &str) -> std::path::PathBuf {
+    static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    let n = COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    std::env::temp_dir().join(format!(
+        "cargo-test-{}-{}-{}.{}",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|v| v.as_nanos())
+            .unwrap_or(0),
+        n,
+        ext
+    ))
+}
+
+#[test]
+fn memory_storage_test() {
+    use crate::schema::Post;
+    use crate::storage::{memory::MemoryStorage, Storage};
+
+    let mut s = MemoryStorage::default();
+
+    s.append(Post::new("first", 1)).unwrap();
+    s.append(Post::new("second", 2)).unwrap();
+
+    // appendでmax_numが追従する.
+    let data = s.load().unwrap();
+    assert_eq!(data.max_num, 2);
+    assert_eq!(data.posts.len(), 2);
+
+    let mut post = data.posts[0].clone();
+    post.revise("edited");
+    s.update(post).unwrap();
+
+    // 同じnumのpostが置き換わる.
+    let data = s.load().unwrap();
+    assert_eq!(data.posts.len(), 2);
+    assert_eq!(data.posts[0].content, "edited");
+    assert_eq!(data.posts[0].revisions[0].content, "first");
+
+    // 存在しないnumはupdateできない.
+    assert!(s.update(Post::new("none", 99)).is_err());
+}
+
+#[test]
+fn toml_storage_test() {
+    use crate::schema::{Post, Schema};
+    use crate::storage::{toml_file::TomlStorage, Storage};
+
+    let path = temp_path("toml");
+
+    let mut s = TomlStorage::new(&path);
+
+    s.save(Schema {
+        user: "test".to_string(),
+        max_num: 0,
+        posts: vec![],
+    })
+    .unwrap();
+    s.append(Post::new("first", 1)).unwrap();
+
+    // ファイル経由でも読み戻せる.
+    let data = s.load().unwrap();
+    assert_eq!(data.user, "test");
+    assert_eq!(data.max_num, 1);
+    assert_eq!(data.posts[0].content, "first");
+
+    // 上書き前の内容は.bakに残る.
+    let mut bak = path.clone().into_os_string();
+    bak.push(".bak");
+    let mut buf = String::new();
+    std::fs::File::open(bak)
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    assert!(buf.contains(r#"user = "test""#));
+    assert!(!buf.contains("first"));
+}
+
+#[test]
+fn backup_test() {
+    use crate::constant::BACKUP_GENERATIONS;
+    use crate::schema::Schema;
+
+    let path = temp_path("toml");
+    let with_suffix = |suffix: &str| {
+        let mut p = path.clone().into_os_string();
+        p.push(suffix);
+        std::path::PathBuf::from(p)
+    };
+    let max_num_of = |p: std::path::PathBuf| {
+        let v = toml::from_str::<toml::Value>(std::fs::read_to_string(p).unwrap().as_str());
+        v.unwrap()["max_num"].as_integer().unwrap()
+    };
+
+    for n in 0..5 {
+        let data = Schema {
+            user: "test".to_string(),
+            max_num: n,
+            posts: vec![],
+        };
+        crate::serde::ser(&path, data).unwrap();
+    }
+
+    // 新しい順に.bak, .bak.1, .bak.2と残り, それより古いものは消える.
+    assert_eq!(BACKUP_GENERATIONS, 3);
+    assert_eq!(max_num_of(path.clone()), 4);
+    assert_eq!(max_num_of(with_suffix(".bak")), 3);
+    assert_eq!(max_num_of(with_suffix(".bak.1")), 2);
+    assert_eq!(max_num_of(with_suffix(".bak.2")), 1);
+    assert!(!with_suffix(".bak.3").exists());
+    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+    let tmp = std::fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .filter_map(|v| v.ok())
+        .map(|v| v.file_name().to_string_lossy().to_string())
+        .filter(|v| v.starts_with(name.as_str()) && v.ends_with(".tmp"))
+        .count();
+    assert_eq!(tmp, 0);
+
+    // 最後に読んだ後に他で書き換えられたファイルは上書きしない.
+    crate::serde::write_atomic(&path, b"user = \"other\"\nmax_num = 9\nposts = []\n").unwrap();
+    let data = Schema {
+        user: "test".to_string(),
+        max_num: 5,
+        posts: vec![],
+    };
+    let e = crate::serde::ser(&path, data).err().unwrap();
+    assert!(matches!(e, crate::error::Error::Changed(_)));
+    assert_eq!(max_num_of(path.clone()), 9);
+    assert_eq!(max_num_of(with_suffix(".bak")), 3);
+
+    for suffix in ["", ".bak", ".bak.1", ".bak.2"] {
+        let _ = std::fs::remove_file(with_suffix(suffix));
+    }
+}
+
+#[test]
+fn lock_test() {
+    let dir = temp_path("d");
+    // 無いディレクトリも作られる.
+    let path = dir.join("posts.toml");
+    let lock_path = dir.join("posts.toml.lock");
+
+    let try_lock = || {
+        let f = std::fs::File::open(&lock_path).unwrap();
+        fs2::FileExt::try_lock_exclusive(&f).is_ok()
+    };
+
+    // 持っている間は他から取れず, 手放すと取れる.
+    let lock = crate::serde::lock(&path).unwrap();
+    assert!(!try_lock());
+    drop(lock);
+    assert!(try_lock());
+
+    // 他が持っている間は待ち, 手放されてから取れる.
+    let other = std::fs::File::open(&lock_path).unwrap();
+    fs2::FileExt::lock_exclusive(&other).unwrap();
+    let released = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let waiter = {
+        let path = path.clone();
+        let released = released.clone();
+        std::thread::spawn(move || {
+            let _lock = crate::serde::lock(&path).unwrap();
+            released.load(std::sync::atomic::Ordering::SeqCst)
+        })
+    };
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    released.store(true, std::sync::atomic::Ordering::SeqCst);
+    fs2::FileExt::unlock(&other).unwrap();
+    assert!(waiter.join().unwrap());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn sqlite_storage_test() {
+    use crate::schema::{Post, Schema};
+    use crate::storage::{sqlite::SqliteStorage, Storage};
+
+    let path = temp_path("db");
+
+    let mut s = SqliteStorage::new(&path);
+
+    // initされていないdbは読めない.
+    assert!(s.load().is_err());
+    assert!(s.max_num().is_err());
+
+    s.save(Schema {
+        user: "test".to_string(),
+        max_num: 0,
+        posts: vec![],
+    })
+    .unwrap();
+    s.append(Post::new("first", 1)).unwrap();
+
+    let mut post = Post::new("second", 2);
+    s.append(post.clone()).unwrap();
+    post.revise("edited");
+    s.update(post).unwrap();
+
+    // 存在しないpostは更新できない.
+    assert!(s.update(Post::new("none", 3)).is_err());
+
+    // max_numはpostを読まずにmetaから得られる.
+    assert_eq!(s.max_num().unwrap(), 2);
+
+    // 別の接続からも読み戻せる.
+    let data = SqliteStorage::new(&path).load().unwrap();
+    assert_eq!(data.user, "test");
+    assert_eq!(data.max_num, 2);
+    assert_eq!(data.posts[0].content, "first");
+    assert_eq!(data.posts[1].content, "edited");
+    assert_eq!(data.posts[1].revisions[0].content, "second");
+    let _ = std::fs::remove_file(&path);
+
+    // uuidやtagsの列が無い古いdbも, 列を足して読める.
+    let path = temp_path("db");
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+         CREATE TABLE posts (num INTEGER PRIMARY KEY, content TEXT NOT NULL, created TEXT NOT NULL,
+                             updated TEXT, is_deleted INTEGER, revisions TEXT);
+         INSERT INTO meta VALUES ('user', 'old'), ('max_num', '1');
+         INSERT INTO posts VALUES (1, 'old post', '2020-01-01T00:00:00Z', NULL, NULL, NULL);",
+    )
+    .unwrap();
+    drop(conn);
+    let data = SqliteStorage::new(&path).load().unwrap();
+    assert_eq!(data.posts[0].content, "old post");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn jsonl_storage_test() {
+    use crate::schema::{Post, Schema};
+    use crate::storage::{jsonl::JsonlStorage, Storage};
+
+    let path = temp_path("jsonl");
+
+    let mut s = JsonlStorage::new(&path);
+
+    s.save(Schema {
+        user: "test".to_string(),
+        max_num: 0,
+        posts: vec![],
+    })
+    .unwrap();
+    let mut post = Post::new("first", 1);
+    s.append(post.clone()).unwrap();
+    post.revise("edited");
+    s.update(post).unwrap();
+
+    // 1 eventにつき1行追記される.
+    let mut buf = String::new();
+    std::fs::File::open(&path)
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    assert_eq!(buf.lines().count(), 3);
+
+    // 途中で切れた最終行は無視され, 次の追記で捨てられる.
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(br#"{"at":"#)
+        .unwrap();
+    assert_eq!(s.load().unwrap().posts.len(), 1);
+    s.append(Post::new("second", 2)).unwrap();
+
+    let data = JsonlStorage::new(&path).load().unwrap();
+    assert_eq!(data.max_num, 2);
+    assert_eq!(data.posts[0].content, "edited");
+    assert_eq!(data.posts[0].revisions[0].content, "first");
+    assert_eq!(data.posts[1].content, "second");
+
+    // 改行だけが無い完全な最終行は捨てずに, 改行を補ってから追記する.
+    let buf = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, buf.trim_end_matches('\n')).unwrap();
+    s.append(Post::new("third", 3)).unwrap();
+
+    let data = JsonlStorage::new(&path).load().unwrap();
+    assert_eq!(data.max_num, 3);
+    assert_eq!(data.posts[1].content, "second");
+    assert_eq!(data.posts[2].content, "third");
+}
+
+#[test]
+fn jsonl_snapshot_test() {
+    use crate::schema::{Post, Schema};
+    use crate::storage::{jsonl::JsonlStorage, Storage};
+
+    let path = temp_path("jsonl");
+    let snapshot = path.with_extension("snapshot.json");
+    let schema = |user: &str| Schema {
+        user: user.to_string(),
+        max_num: 0,
+        posts: vec![],
+    };
+    let fill = |s: &mut JsonlStorage| {
+        for n in 1..=crate::constant::SNAPSHOT_INTERVAL as u32 {
+            s.append(Post::new(format!("post {}", n), n)).unwrap();
+        }
+    };
+
+    // 十分な数のeventを読むとsnapshotが作られ, 次からはその続きだけを読む.
+    let mut s = JsonlStorage::new(&path);
+    s.save(schema("me")).unwrap();
+    fill(&mut s);
+    assert_eq!(s.load().unwrap().max_num, 100);
+    assert!(snapshot.exists());
+    s.append(Post::new("after snapshot", 101)).unwrap();
+    assert_eq!(s.load().unwrap().posts[100].content, "after snapshot");
+
+    // initはsnapshotを消す.
+    std::fs::remove_file(&path).unwrap();
+    s.save(schema("fresh")).unwrap();
+    assert!(!snapshot.exists());
+    let data = s.load().unwrap();
+    assert_eq!(data.user, "fresh");
+    assert!(data.posts.is_empty());
+
+    // 別のlogのsnapshotが残っていても使わずに消す. (短いlogも, 同じ長さの違うlogも)
+    fill(&mut s);
+    s.load().unwrap();
+    let stale = std::fs::read(&snapshot).unwrap();
+    let stale_len = std::fs::metadata(&path).unwrap().len();
+    std::fs::write(&path, "").unwrap();
+    s.save(schema("other")).unwrap();
+    std::fs::write(&snapshot, &stale).unwrap();
+    let data = s.load().unwrap();
+    assert_eq!(data.user, "other");
+    assert!(data.posts.is_empty());
+    assert!(!snapshot.exists());
+
+    std::fs::write(&path, "").unwrap();
+    s.save(schema("again")).unwrap();
+    fill(&mut s);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), stale_len);
+    std::fs::write(&snapshot, &stale).unwrap();
+    assert_eq!(s.load().unwrap().user, "again");
+
+    // snapshotが書けなくても読み込みは失敗しない.
+    std::fs::remove_file(&snapshot).unwrap();
+    std::fs::create_dir(&snapshot).unwrap();
+    let data = s.load().unwrap();
+    assert_eq!(data.user, "again");
+    assert_eq!(data.max_num, 100);
+
+    let _ = std::fs::remove_dir(&snapshot);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn error_test() {
+    use crate::error::Error;
+    use crate::schema::PostForSerde;
+
+    // 存在しないファイルはpanicせずNotFoundになる.
+    let e = match crate::serde::de(std::path::Path::new("/nonexistent/posts.toml")) {
+        Ok(_) => panic!("must fail"),
+        Err(e) => e,
+    };
+    assert!(matches!(e, Error::NotFound(_)));
+
+    // 壊れたtimestampはどのpostのどのfieldかを報告する.
+    let e = crate::serde::post_from_dfsd(PostForSerde {
+        num: 3,
+        uuid: None,
+        content: "".to_string(),
+        created: "2020-01-01T00:00:00Z".to_string(),
+        updated: Some("yesterday".to_string()),
+        is_deleted: None,
+        tags: None,
+        revisions: None,
+    })
+    .unwrap_err();
+    assert!(matches!(&e, Error::Timestamp { num: 3, field, .. } if field == "updated"));
+
+    // storageの失敗も種類で区別できる.
+    use crate::storage::Storage;
+    let e = crate::storage::memory::MemoryStorage::default()
+        .update(crate::schema::Post::new("missing", 1))
+        .unwrap_err();
+    assert!(matches!(e, Error::MissingPost(1)));
+
+    let path = temp_path("jsonl");
+    std::fs::write(&path, "").unwrap();
+    let e = crate::storage::jsonl::JsonlStorage::new(&path)
+        .load()
+        .err()
+        .unwrap();
+    assert!(matches!(e, Error::NotInitialized(_)));
+    std::fs::write(&path, "{broken\n{\"event\": \"reset\"}\n").unwrap();
+    let e = crate::storage::jsonl::JsonlStorage::new(&path)
+        .load()
+        .err()
+        .unwrap();
+    assert!(matches!(e, Error::Json { line: Some(1), .. }));
+    let _ = std::fs::remove_file(&path);
+
+    let path = temp_path("db");
+    let e = crate::storage::sqlite::SqliteStorage::new(&path)
+        .load()
+        .err()
+        .unwrap();
+    assert!(matches!(e, Error::NotFound(_)));
+    crate::storage::sqlite::SqliteStorage::new(&path)
+        .save_journal(&Default::default())
+        .unwrap();
+    let e = crate::storage::sqlite::SqliteStorage::new(&path)
+        .load()
+        .err()
+        .unwrap();
+    assert!(matches!(e, Error::NotInitialized(_)));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn check_test() {
+    let mut data = toml::from_str::<toml::Value>(
+        r#"
+user = "test"
+max_num = 1
+
+[[posts]]
+num = 1
+content = "a"
+created = "2020-01-02T00:00:00Z"
+updated = "2020-01-01T00:00:00Z"
+
+[[posts]]
+num = 1
+content = "b"
+created = "yesterday"
+"#,
+    )
+    .unwrap();
+
+    // 場所付きで全ての問題を報告する.
+    let problems = crate::check::inspect(&data);
+    let locations = problems
+        .iter()
+        .map(|p| p.location.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        locations,
+        vec![
+            "posts[0] (num = 1).updated",
+            "posts[1] (num = 1).num",
+            "posts[1] (num = 1).created",
+        ]
+    );
+
+    // 重複したnumは振り直され, max_numも追従する.
+    let fixed = crate::check::fix(&mut data);
+    assert_eq!(fixed.len(), 2);
+    assert_eq!(data["posts"][1]["num"].as_integer(), Some(2));
+    assert_eq!(data["max_num"].as_integer(), Some(2));
+    assert!(crate::check::inspect(&data).iter().all(|p| !p.fixable));
+}
+
+#[test]
+fn check_fix_test() {
+    with_file("toml", |path| {
+        std::fs::write(
+            path,
+            r#"user = "test"
+max_num = 1
+
+[[posts]]
+num = 1
+content = "a"
+created = "2020-01-01T00:00:00Z"
+
+[[posts]]
+num = 1
+content = "b"
+created = "yesterday"
+"#,
+        )
+        .unwrap();
+
+        // 直せない問題が残っていても, 直したものは保存し, 残りはそのまま.
+        assert_eq!(run("check --fix"), 1);
+        let raw = crate::serde::de_value(path).unwrap();
+        assert_eq!(raw["posts"][1]["num"].as_integer(), Some(2));
+        assert_eq!(raw["max_num"].as_integer(), Some(2));
+        assert_eq!(raw["posts"][1]["created"].as_str(), Some("yesterday"));
+    });
+
+    with_file("db", |path| {
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("post a"), 0);
+        assert_eq!(run("post b"), 0);
+        rusqlite::Connection::open(path)
+            .unwrap()
+            .execute_batch(
+                "UPDATE posts SET tags = '{broken' WHERE num = 1;
+                 UPDATE posts SET created = 'yesterday' WHERE num = 2;
+                 UPDATE meta SET value = '0' WHERE key = 'max_num';",
+            )
+            .unwrap();
+
+        // 壊れたpostがあっても, 残りのpostの問題も全て報告する.
+        let raw = crate::storage::with(|s| s.load_raw()).ok().unwrap();
+        let problems = crate::check::inspect(&raw.data);
+        let locations = problems
+            .iter()
+            .map(|p| p.location.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            vec![
+                "max_num",
+                "posts[0] (num = 1).tags",
+                "posts[1] (num = 2).created"
+            ]
+        );
+
+        assert_eq!(run("check --fix"), 1);
+        let raw = crate::storage::with(|s| s.load_raw()).ok().unwrap();
+        assert_eq!(raw.data["max_num"].as_integer(), Some(2));
+        assert_eq!(raw.data["posts"][0]["tags"].as_str(), Some("{broken"));
+    });
+
+    with_file("jsonl", |path| {
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("post a"), 0);
+        assert_eq!(run("post b"), 0);
+        let mut lines = std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>();
+        lines.insert(2, "{broken".to_string());
+        std::fs::write(path, lines.join("\n") + "\n").unwrap();
+
+        // 途中の壊れた行は行番号付きで報告し, 後の行も読む.
+        let raw = crate::storage::with(|s| s.load_raw()).ok().unwrap();
+        assert_eq!(raw.unreadable.len(), 1);
+        assert_eq!(raw.unreadable[0].location, "line 3");
+        assert_eq!(raw.data["posts"].as_array().map(|v| v.len()), Some(2));
+        assert_eq!(run("check"), 1);
+    });
+}
+
+#[test]
+fn repair_test() {
+    let r = crate::repair::repair(
+        r#"user = "test"
+max_num = 2
+
+[[posts]]
+num = 1
+content = "broken
+created = "2020-01-01T00:00:00Z"
+
+[[posts]]
+num = 2
+content = "valid"
+created = "2020-01-01T00:00:00Z"
+
+[[posts.revisions]]
+content = "old"
+replaced = "2020-01-01T00:00:00Z"
+
+[[posts]]
+num = 2
+content = "duplicated"
+created = "2020-01-01T00:00:00Z"
+"#,
+    )
+    .unwrap();
+
+    // 壊れたpostだけが隔離され, 行番号が残る.
+    assert_eq!(r.salvaged, 2);
+    assert_eq!(r.quarantined.len(), 1);
+    assert_eq!(r.quarantined[0].line, 4);
+    assert!(r.quarantined[0].text.contains(r#"content = "broken"#));
+
+    // revisionsは元のpostに残り, 重複したnumは振り直される.
+    assert_eq!(r.data.user, "test");
+    assert_eq!(r.data.posts[0].revisions[0].content, "old");
+    assert_eq!(r.data.posts[1].num, 3);
+    assert_eq!(r.data.max_num, 3);
+
+    // 複数行文字列の中の[[posts]]はpostの区切りではない.
+    let r = crate::repair::repair(
+        r#"user = "test"
+max_num = 2
+
+[[posts]]
+num = 1
+content = """
+[[posts]]
+\""" # not closed
+'''
+[[posts]]
+"""
+created = "2020-01-01T00:00:00Z"
+
+[[posts]]
+num = 2
+content = '''
+[[posts]]
+'''
+created = "2020-01-01T00:00:00Z"
+"#,
+    )
+    .unwrap();
+    assert_eq!(r.salvaged, 2);
+    assert!(r.quarantined.is_empty());
+    assert_eq!(
+        r.data.posts[0].content,
+        "[[posts]]\n\"\"\" # not closed\n'''\n[[posts]]\n"
+    );
+    assert_eq!(r.data.posts[1].content, "[[posts]]\n");
+
+    // 文字列の中の非ascii文字でも落ちない.
+    let r = crate::repair::repair(
+        r#"user = "テスト"
+max_num = 1
+
+[[posts]]
+num = 1
+content = """
+こんにちは 'é' "ü"
+[[posts]]
+"""
+tags = ['タグ']
+created = "2020-01-01T00:00:00Z"
+"#,
+    )
+    .unwrap();
+    assert_eq!(r.salvaged, 1);
+    assert_eq!(r.data.posts[0].content, "こんにちは 'é' \"ü\"\n[[posts]]\n");
+}
+
+#[test]
+fn export_test() {
+    use crate::export::{render, Format};
+    use crate::filter::Filter;
+    use crate::schema::Post;
+
+    let mut p1 = Post::new("<a> & b", 1);
+    p1.created = crate::filter::parse_date("2020-01-01", false).unwrap();
+    let p2 = Post::new("line1\nline2", 2);
+
+    // 日付だけのuntilはその日の終わりまでを含む.
+    let mut f = Filter::default();
+    f.set("--until", "2020-01-01").unwrap();
+    assert!(f.is_match(&p1));
+    assert!(!f.is_match(&p2));
+    assert!(f.set("--from", "x").is_err());
+    assert!(!f.set("--unknown", "1").unwrap());
+
+    // rfc3339のsinceとuntilは, ちょうどその時刻も含む.
+    let mut p3 = Post::new("boundary", 3);
+    p3.created = crate::filter::parse_date("2020-01-01T12:00:00Z", false).unwrap();
+    let mut f = Filter::default();
+    f.set("--since", "2020-01-01T12:00:00Z").unwrap();
+    f.set("--until", "2020-01-01T12:00:00Z").unwrap();
+    assert!(f.is_match(&p3));
+    p3.created = p3.created + chrono::Duration::nanoseconds(1);
+    assert!(!f.is_match(&p3));
+
+    // htmlはescapeされ, 改行は保たれる.
+    let html = render(&Format::Html, "test", &[&p1, &p2]).unwrap();
+    assert!(html.contains("&lt;a&gt; &amp; b"));
+    assert!(html.contains("line1<br>\nline2"));
+
+    let json = render(&Format::Json, "test", &[&p1]).unwrap();
+    let v = serde_json::from_str::<serde_json::Value>(json.as_str()).unwrap();
+    assert_eq!(v[0]["num"], 1);
+}
+
+#[test]
+fn import_test() {
+    use crate::import::{from_csv, from_json, from_text};
+
+    // 空行区切り, 前後の空白は落とす.
+    let posts = from_text("a\n  b  \n\n\n  c\n");
+    let contents = posts.iter().map(|v| v.content.as_str()).collect::<Vec<_>>();
+    assert_eq!(contents, vec!["a\n  b", "c"]);
+
+    // createdがあれば保たれ, exportしたjsonの余計なkeyは無視する.
+    let posts =
+        from_json(r#"["a", {"num": 9, "content": "b", "created": "2020-01-01T00:00:00Z"}]"#)
+            .unwrap();
+    assert!(posts[0].created.is_none());
+    assert_eq!(posts[1].created.unwrap().timestamp(), 1577836800);
+    assert!(from_json(r#"[{"created": "2020-01-01"}]"#).is_err());
+
+    let posts = from_csv("created,content\n,\"a, b\"\nbad,c\n");
+    assert!(matches!(posts, Err(e) if e.to_string().starts_with("line 3")));
+    let posts = from_csv("content\n\"multi\nline\"\n").unwrap();
+    assert_eq!(posts[0].content, "multi\nline");
+}
+
+#[test]
+fn merge_test() {
+    use crate::schema::{Post, Schema};
+
+    let shared = (1..=3).map(|n| Post::new(n, n)).collect::<Vec<_>>();
+    let schema = |posts: Vec<Post>| Schema {
+        user: "test".to_string(),
+        max_num: posts.iter().map(|v| v.num).max().unwrap_or(0),
+        posts: posts.into_iter().collect(),
+    };
+
+    let mut ours = shared.clone();
+    ours[1].revise("ours");
+    ours[2].revise("ours");
+    ours.push(Post::new("ours only", 4));
+
+    let mut theirs = shared;
+    theirs[0].revise("theirs");
+    theirs[2].revise("theirs");
+    theirs.push(Post::new("theirs only", 4));
+
+    let mut local = schema(ours);
+    let report = crate::merge::merge(&mut local, schema(theirs));
+
+    // 片側だけの編集は取り込み, 両側の編集は衝突として残す.
+    assert_eq!(local.posts[0].content, "theirs");
+    assert_eq!(local.posts[1].content, "ours");
+    assert_eq!(local.posts[2].content, "ours");
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(report.conflicts[0].num, 3);
+
+    // numが衝突した新しいpostは振り直される.
+    assert_eq!(local.posts[4].content, "theirs only");
+    assert_eq!(local.posts[4].num, 5);
+    assert_eq!(local.max_num, 5);
+    assert_eq!(report.changes.len(), 2);
+    assert_eq!(report.same, 1);
+}
+
+#[test]
+fn migrate_test() {
+    with_file("toml", |path| {
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("post first"), 0);
+
+        // 自分自身へはmigrateできない. (書き方が違っても同じファイル)
+        let same = path
+            .parent()
+            .unwrap()
+            .join(".")
+            .join(path.file_name().unwrap());
+        let to_self = format!("migrate {} {}", path.display(), same.display());
+        assert_eq!(run(to_self.as_str()), 2);
+        assert_eq!(load().posts.len(), 1);
+
+        // 別のファイルへは投稿ごと移せる.
+        let db = path.with_extension("db");
+        let to_db = format!("migrate {} {}", path.display(), db.display());
+        assert_eq!(run(to_db.as_str()), 0);
+        let data = crate::storage::open(&db).load().ok().unwrap();
+        assert_eq!(data.posts[0].content, "first");
+    });
+}
+
+#[test]
+fn merge_self_test() {
+    with_file("toml", |path| {
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("post first"), 0);
+
+        // 今のファイル自身とはmergeできない. (書き方が違っても同じファイル)
+        let same = path
+            .parent()
+            .unwrap()
+            .join(".")
+            .join(path.file_name().unwrap());
+        assert_eq!(run(format!("merge {}", path.display()).as_str()), 2);
+        assert_eq!(run(format!("merge {}", same.display()).as_str()), 2);
+        assert_eq!(load().posts.len(), 1);
+
+        // 別のファイルならmergeできる.
+        let other = path.with_extension("other.toml");
+        std::fs::copy(path, &other).unwrap();
+        assert_eq!(run(format!("merge {}", other.display()).as_str()), 0);
+        assert_eq!(load().posts.len(), 1);
+    });
+}
+
+#[test]
+fn uuid_test() {
+    use crate::schema::{Post, PostRef};
+
+    let mut p = Post::new("a", 1);
+    p.uuid = uuid::Uuid::parse_str("3f2a9c1e-0b7d-4e21-9a53-1c2d3e4f5a6b").unwrap();
+
+    // 数字だけならnum, それ以外はuuidの先頭.
+    assert_eq!("12".parse::<PostRef>(), Ok(PostRef::Num(12)));
+    assert_eq!(
+        "3f2a".parse::<PostRef>(),
+        Ok(PostRef::Uuid("3f2a".to_string()))
+    );
+    assert_eq!(
+        "3F2A9C1E-0B7D".parse::<PostRef>(),
+        Ok(PostRef::Uuid("3f2a9c1e0b7d".to_string()))
+    );
+    assert!("3f2a".parse::<PostRef>().unwrap().is_match(&p));
+    assert!("3F2A9C1E-0B7D".parse::<PostRef>().unwrap().is_match(&p));
+    assert!(!"3f2b".parse::<PostRef>().unwrap().is_match(&p));
+    assert!("abc".parse::<PostRef>().is_err());
+    assert!("xyz0".parse::<PostRef>().is_err());
+
+    // 数字だけのuuidの先頭は"u:"を付けるか, 0から始める.
+    assert_eq!(
+        "u:1234".parse::<PostRef>(),
+        Ok(PostRef::Uuid("1234".to_string()))
+    );
+    assert_eq!(
+        "0123".parse::<PostRef>(),
+        Ok(PostRef::Uuid("0123".to_string()))
+    );
+    assert!("u:12".parse::<PostRef>().is_err());
+    // 4桁以上のnumは, 該当するpostが無ければuuidの先頭として探し直す.
+    assert_eq!(
+        PostRef::Num(1234).uuid_fallback(),
+        Some(PostRef::Uuid("1234".to_string()))
+    );
+    assert_eq!(PostRef::Num(123).uuid_fallback(), None);
+
+    // uuidの無い古いpostは, 毎回同じuuidを得る.
+    let mut old = crate::serde::post_to_dfsd(p);
+    old.uuid = None;
+    let a = crate::serde::post_from_dfsd(old.clone()).unwrap();
+    let b = crate::serde::post_from_dfsd(old.clone()).unwrap();
+    assert_eq!(a.uuid, b.uuid);
+    assert_eq!(a.uuid.get_version_num(), 8);
+
+    // createdが同じでもnumが違えば別のuuidになる.
+    old.num = 2;
+    let c = crate::serde::post_from_dfsd(old).unwrap();
+    assert_ne!(a.uuid, c.uuid);
+}
+
+#[test]
+fn tags_test() {
+    use crate::schema::{impls, Post};
+
+    // 単語の先頭の#だけがタグになり, 小文字に揃えられる.
+    let mut p = Post::new("#Standup done. see issue#3 and #bug-fix\n#idea", 1);
+    assert_eq!(
+        p.tags.iter().map(|v| v.as_str()).collect::<Vec<_>>(),
+        vec!["bug-fix", "idea", "standup"]
+    );
+
+    // 編集で消えたハッシュタグは外れるが, 手で付けたタグは残る.
+    p.tags.insert("manual".to_string());
+    p.revise("#standup again");
+    assert_eq!(
+        p.tags.iter().map(|v| v.as_str()).collect::<Vec<_>>(),
+        vec!["manual", "standup"]
+    );
+
+    assert_eq!(impls::normalize_tag("#Idea"), Ok("idea".to_string()));
+    assert!(impls::normalize_tag("a b").is_err());
+    assert!(impls::normalize_tag("#").is_err());
+
+    // 保存して読み直してもタグは同じ.
+    let read = crate::serde::post_from_dfsd(crate::serde::post_to_dfsd(p.clone())).unwrap();
+    assert_eq!(read.tags, p.tags);
+
+    let mut f = crate::filter::Filter::default();
+    assert_eq!(f.set("--tag", "#STANDUP"), Ok(true));
+    assert!(f.is_match(&p));
+    assert_eq!(f.set("--tag", "idea"), Ok(true));
+    assert!(!f.is_match(&p));
+}
+
+#[test]
+fn order_test() {
+    use crate::filter::{DateField, Filter, Order};
+    use crate::schema::Post;
+
+    let day = |d: u32| crate::filter::parse_date(&format!("2020-01-{:02}", d), false).unwrap();
+    let post = |num, created, updated: Option<u32>| {
+        let mut p = Post::new("", num);
+        p.created = day(created);
+        if let Some(u) = updated {
+            p.revise("edited");
+            p.revisions[0].replaced = day(u);
+        }
+        p
+    };
+    let mut posts = [post(1, 3, None), post(2, 1, Some(5)), post(3, 2, None)];
+    // 削除や復元で変わるupdatedは, 編集日時ではない.
+    posts[2].updated = Some(day(9));
+    let nums = |v: &[&Post]| v.iter().map(|p| p.num).collect::<Vec<_>>();
+
+    // 更新日時で並べ替え. 編集されていないpostは作成日時を使う.
+    let mut v = posts.iter().collect::<Vec<_>>();
+    let mut o = Order::default();
+    assert_eq!(o.set("--sort", "updated"), Ok(true));
+    assert!(o.set_flag("--reverse"));
+    o.sort(v.as_mut_slice());
+    assert_eq!(nums(&v), vec![2, 1, 3]);
+    assert!(o.set("--sort", "size").is_err());
+
+    // 期間は--dateで選んだ日時に対して絞り込む.
+    let mut f = Filter::default();
+    f.set("--since", "2020-01-03").unwrap();
+    let matched = |f: &Filter| nums(&posts.iter().filter(|p| f.is_match(p)).collect::<Vec<_>>());
+    assert_eq!(matched(&f), vec![1]);
+    f.set("--date", "updated").unwrap();
+    assert_eq!(f.date, DateField::Updated);
+    assert_eq!(matched(&f), vec![1, 2]);
+    assert!(f.set_flag("--edited-only"));
+    assert_eq!(matched(&f), vec![2]);
+}
+
+#[test]
+fn search_test() {
+    use crate::commands::search_command_parse;
+
+    let matcher = |line: &str| {
+        search_command_parse(line.split(' ').collect())
+            .ok()
+            .unwrap()
+            .0
+    };
+
+    // 空白を含むqueryはそのまま繋がる.
+    let (m, query) = search_command_parse(smallvec::smallvec!["sub", "Foo", "bar"])
+        .ok()
+        .unwrap();
+    assert_eq!(query, "Foo bar");
+    assert!(m.is_match("a Foo bar b"));
+    assert!(!m.is_match("a foo bar b"));
+
+    // isubは大文字小文字を区別しない.
+    let m = matcher("isub FoO");
+    assert!(m.is_match("xfooy"));
+    assert!(m.is_match("XFOOY"));
+    assert!(!m.is_match("fo o"));
+
+    let m = matcher("regex ^a.c$");
+    assert!(m.is_match("abc"));
+    assert!(!m.is_match("abcd"));
+
+    // 壊れた正規表現や不明なmodeはusageエラーになる.
+    let e = search_command_parse(smallvec::smallvec!["regex", "("])
+        .err()
+        .unwrap();
+    assert!(e.starts_with("parse error (query):"));
+    assert!(search_command_parse(smallvec::smallvec!["glob", "*"]).is_err());
+    assert!(search_command_parse(smallvec::smallvec!["sub"]).is_err());
+
+    with_file("toml", |_| {
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("post hello"), 0);
+        assert_eq!(run("search regex h.llo"), 0);
+        assert_eq!(run("search regex ("), 2);
+        assert_eq!(run("search glob *"), 2);
+    });
+}
+
+#[test]
+fn page_test() {
+    use crate::commands::{page_count, page_range};
+
+    // 最後のページは短くてもよい.
+    assert_eq!(page_count(25, 10), 3);
+    assert_eq!(page_range(25, 10, 3), Ok(20..25));
+    assert!(page_range(25, 10, 4).is_err());
+    assert!(page_range(25, 10, 0).is_err());
+
+    // 空でも1ページ目は表示できる.
+    assert_eq!(page_count(0, 10), 1);
+    assert_eq!(page_range(0, 10, 1), Ok(0..0));
+}
+
+#[test]
+fn completion_test() {
+    use rustyline::completion::Completer;
+
+    let h = rustyline::history::DefaultHistory::new();
+    let ctx = rustyline::Context::new(&h);
+    let complete = |line: &str| {
+        let (start, v) = crate::repl::ReplHelper
+            .complete(line, line.len(), &ctx)
+            .unwrap();
+        (
+            start,
+            v.into_iter().map(|p| p.replacement).collect::<Vec<_>>(),
+        )
+    };
+
+    // prefixの後のコマンド名を補完する.
+    assert_eq!(
+        complete(":tag"),
+        (1, vec!["tag ".to_string(), "tags ".to_string()])
+    );
+    assert_eq!(complete(":re").1.len(), 5);
+    // 普通の投稿は補完しない.
+    assert_eq!(complete("ta"), (2, vec![]));
+    // numを取らないコマンドの引数も補完しない.
+    assert_eq!(complete(":show 1"), (6, vec![]));
+}
+
+#[test]
+fn nested_storage_test() {
+    use crate::storage::memory::MemoryStorage;
+    use crate::storage::Storage;
+
+    // ":memory:"として開いたものは全て同じpostを見る.
+    let mut a = MemoryStorage::shared();
+    let mut b = MemoryStorage::shared();
+    let before = a.load().unwrap().posts.len();
+    b.append(crate::schema::Post::new("shared", 9999)).unwrap();
+    assert_eq!(a.load().unwrap().posts.len(), before + 1);
+
+    // withの中からwithを呼んでもdeadlockしない.
+    with_file("toml", |_| {
+        let n = crate::storage::with(|_| crate::storage::with(|_| 1));
+        assert_eq!(n, 1);
+    });
+}
+
+/// runs `f` with "posts.{ext}" in fresh temp dir as current file.
+/// commands share current file and output format, so that these tests run one by one.
+#[cfg(test)]
+pub fn with_file(ext: &str, f: impl FnOnce(&std::path::Path)) {
+    lazy_static::lazy_static! {
+        static ref LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    }
+    // failed test must not fail others.
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let dir = temp_path("d");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("posts.{}", ext));
+    crate::config::set_toml_path(&path);
+    crate::output::set_format(crate::output::Format::Text);
+    crate::output::take_failure();
+
+    f(&path);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn config_path_test() {
+    use crate::config::default_toml_path;
+    use crate::constant::{DATA_DIR_NAME, DEFAULT_FILE_NAME, PATH_ENV};
+
+    // ./posts.toml が無い所で実行される前提.
+    assert!(!std::path::Path::new(DEFAULT_FILE_NAME).exists());
+
+    with_file("toml", |_| {
+        let saved = ["XDG_DATA_HOME", PATH_ENV].map(std::env::var_os);
+        let data_home = temp_path("d");
+
+        // 環境変数が無ければ, データディレクトリの下.
+        std::env::set_var("XDG_DATA_HOME", &data_home);
+        std::env::remove_var(PATH_ENV);
+        assert_eq!(
+            default_toml_path(),
+            data_home.join(DATA_DIR_NAME).join(DEFAULT_FILE_NAME)
+        );
+
+        // 空の環境変数は無視され, 値があればそちらが優先される.
+        std::env::set_var(PATH_ENV, "");
+        assert_eq!(
+            default_toml_path(),
+            data_home.join(DATA_DIR_NAME).join(DEFAULT_FILE_NAME)
+        );
+        std::env::set_var(PATH_ENV, "/from/env.toml");
+        assert_eq!(default_toml_path(), std::path::Path::new("/from/env.toml"));
+
+        // --fileは環境変数より優先される. 残りはsubcommandとして返る.
+        let args = vec!["--file", "/from/flag.toml", "show", "--file"];
+        let rest = crate::parse_flags(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(rest, ["show", "--file"]);
+        assert_eq!(
+            crate::config::toml_path(),
+            std::path::Path::new("/from/flag.toml")
+        );
+
+        let args = vec!["--file=/from/eq.toml".to_string()];
+        assert!(crate::parse_flags(args.into_iter()).unwrap().is_empty());
+        assert_eq!(
+            crate::config::toml_path(),
+            std::path::Path::new("/from/eq.toml")
+        );
+
+        // 値の無い--fileや不明なflagはエラー.
+        assert!(crate::parse_flags(vec!["--file".to_string()].into_iter()).is_err());
+        assert!(crate::parse_flags(vec!["--unknown".to_string()].into_iter()).is_err());
+
+        for (key, value) in ["XDG_DATA_HOME", PATH_ENV].iter().zip(saved) {
+            match value {
+                Some(v) => std::env::set_var(key, v),
+                None => std::env::remove_var(key),
+            }
+        }
+    });
+}
+
+/// as same as one-shot mode. (`virtual_lasagna_cli post hello`) returns exit status.
+#[cfg(test)]
+pub fn run(line: &str) -> i32 {
+    crate::one_shot(line.split(' ').map(|v| v.to_string()).collect())
+}
+
+/// current file, which must be loadable.
+#[cfg(test)]
+pub fn load() -> crate::schema::Schema {
+    crate::storage::with(|s| s.load()).ok().unwrap()
+}
+
+#[test]
+fn journal_test() {
+    with_file("toml", |_| {
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("post first"), 0);
+        assert_eq!(run("edit 1 edited"), 0);
+
+        // editを取り消して, やり直す.
+        assert_eq!(run("undo"), 0);
+        assert_eq!(load().posts[0].content, "first");
+        assert_eq!(run("redo"), 0);
+        assert_eq!(load().posts[0].content, "edited");
+
+        // postを取り消すとpostは無くなり, やり直すと同じuuidで戻る.
+        let uuid = load().posts[0].uuid;
+        assert_eq!(run("undo"), 0);
+        assert_eq!(run("undo"), 0);
+        assert!(load().posts.is_empty());
+        assert_eq!(run("undo"), 4);
+        assert_eq!(run("redo"), 0);
+        assert_eq!(load().posts[0].content, "first");
+        assert_eq!(load().posts[0].uuid, uuid);
+
+        // 記録した後に他で変更されたpostには適用しない.
+        let mut data = load();
+        data.posts[0].revise("changed by other");
+        crate::storage::with(|s| s.save(data)).unwrap();
+        assert_eq!(run("undo"), 4);
+        assert_eq!(run("redo"), 4);
+        assert_eq!(load().posts[0].content, "changed by other");
+
+        // purgeした後は何も取り消せない.
+        assert_eq!(run("post second"), 0);
+        assert_eq!(run("remove 2"), 0);
+        assert_eq!(run("purge"), 0);
+        assert_eq!(run("undo"), 4);
+        assert_eq!(load().posts.len(), 1);
+    });
+}
+
+#[test]
+fn restore_test() {
+    with_file("toml", |_| {
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("post first"), 0);
+        assert_eq!(run("post second"), 0);
+
+        // 削除してもpostは残り, 削除日時がupdatedに入る.
+        assert_eq!(run("remove 1"), 0);
+        let post = &load().posts[0];
+        assert_eq!(post.is_deleted, Some(true));
+        assert!(post.updated.is_some());
+        assert_eq!(run("trash"), 0);
+
+        // 二重の削除や, 削除されていないpostの復元は競合.
+        assert_eq!(run("remove 1"), 4);
+        assert_eq!(run("restore 2"), 4);
+        assert_eq!(run("restore 9"), 3);
+
+        // 復元と, その別名.
+        assert_eq!(run("restore 1"), 0);
+        assert!(!load().posts[0].is_deleted.unwrap_or(false));
+        assert_eq!(run("remove 1"), 0);
+        assert_eq!(run("undelete 1"), 0);
+        assert!(!load().posts[0].is_deleted.unwrap_or(false));
+
+        // 復元も取り消せる.
+        assert_eq!(run("undo"), 0);
+        assert_eq!(load().posts[0].is_deleted, Some(true));
+        assert_eq!(load().posts[0].content, "first");
+    });
+}
+
+#[test]
+fn purge_test() {
+    with_file("toml", |_| {
+        assert_eq!(run("init test"), 0);
+        for c in ["old", "unknown", "recent", "alive"] {
+            assert_eq!(run(format!("post {}", c).as_str()), 0);
+        }
+        for n in 1..=3 {
+            assert_eq!(run(format!("remove {}", n).as_str()), 0);
+        }
+
+        let mut data = load();
+        data.posts[0].updated = Some(chrono::Local::now() - chrono::Duration::days(10));
+        data.posts[1].updated = None;
+        crate::storage::with(|s| s.save(data)).unwrap();
+
+        // 日数を指定すると, 削除日時の分からないpostは残す.
+        assert_eq!(run("purge 5"), 0);
+        let nums = load().posts.iter().map(|v| v.num).collect::<Vec<_>>();
+        assert_eq!(nums, [2, 3, 4]);
+
+        // 遡れないほど大きな日数や, u32に収まらない日数はusageエラー.
+        assert_eq!(run("purge 100000000"), 2);
+        assert_eq!(run("purge 4294967296"), 2);
+        assert_eq!(load().posts.len(), 3);
+
+        // 指定しなければ削除されたpostを全て消す.
+        assert_eq!(run("purge"), 0);
+        assert_eq!(load().posts.len(), 1);
+        assert_eq!(load().posts[0].content, "alive");
+    });
+}
+
+#[test]
+fn revert_test() {
+    with_file("toml", |_| {
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("post first"), 0);
+        assert_eq!(run("edit 1 second"), 0);
+        assert_eq!(run("edit 1 third"), 0);
+        assert_eq!(run("history 1"), 0);
+
+        // 古い版に戻しても, 今の内容は新しい版として残る.
+        assert_eq!(run("revert 1 1"), 0);
+        let post = &load().posts[0];
+        assert_eq!(post.content, "first");
+        let revisions = post
+            .revisions
+            .iter()
+            .map(|v| v.content.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(revisions, ["first", "second", "third"]);
+
+        // revertも取り消せる.
+        assert_eq!(run("undo"), 0);
+        assert_eq!(load().posts[0].content, "third");
+        assert_eq!(load().posts[0].revisions.len(), 2);
+
+        // 範囲外の版や存在しないpostはnot found, 0番や余分な引数はusageエラー.
+        assert_eq!(run("revert 1 3"), 3);
+        assert_eq!(run("revert 9 1"), 3);
+        assert_eq!(run("history 9"), 3);
+        assert_eq!(run("revert 1 0"), 2);
+        assert_eq!(run("history 1 2"), 2);
+        assert_eq!(load().posts[0].content, "third");
+    });
+}
+
+#[test]
+fn import_undo_test() {
+    with_file("toml", |path| {
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("post first"), 0);
+
+        // importしたpostは1回でまとめて取り消し, やり直す.
+        let src = path.with_file_name("import.txt");
+        std::fs::write(&src, "a\n\nb\n\nc\n").unwrap();
+        assert_eq!(run(format!("import {}", src.display()).as_str()), 0);
+        assert_eq!(load().posts.len(), 4);
+        assert_eq!(run("undo"), 0);
+        assert_eq!(load().posts.len(), 1);
+        assert_eq!(run("redo"), 0);
+        assert_eq!(load().posts.len(), 4);
+
+        // 1つでも変更されていれば, どれも取り消さない.
+        assert_eq!(run("edit 3 changed"), 0);
+        assert_eq!(run("undo"), 0);
+        let mut data = load();
+        data.posts[2].revise("changed by other");
+        crate::storage::with(|s| s.save(data)).unwrap();
+        assert_eq!(run("undo"), 4);
+        assert_eq!(load().posts.len(), 4);
+    });
+
+    // mergeで取り込んだpostも1回でまとめて取り消す.
+    with_file("toml", |path| {
+        let other = path.with_file_name("other.toml");
+        crate::config::set_toml_path(&other);
+        assert_eq!(run("init other"), 0);
+        assert_eq!(run("post x"), 0);
+        assert_eq!(run("post y"), 0);
+
+        crate::config::set_toml_path(path);
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("post first"), 0);
+        assert_eq!(run(format!("merge {}", other.display()).as_str()), 0);
+        assert_eq!(load().posts.len(), 3);
+        assert_eq!(run("undo"), 0);
+        assert_eq!(load().posts.len(), 1);
+        assert_eq!(run("redo"), 0);
+        assert_eq!(load().posts.len(), 3);
+    });
+
+    // 1件ずつ記録していた頃のjournalも読める.
+    let j = toml::from_str::<crate::journal::Journal>(
+        "[[undo]]\nkind = \"post\"\nnum = 1\n[undo.after]\nnum = 1\ncontent = \"a\"\ncreated = \"2020-01-01T00:00:00Z\"\n",
+    )
+    .ok()
+    .unwrap();
+    assert_eq!(j.undo[0].nums(), vec![1]);
+}
+
+#[test]
+fn compose_test() {
+    use crate::schema::PostRef;
+    use crate::Interactive;
+
+    // 入力行の振り分け.
+    assert_eq!(
+        crate::classify_interactive(":compose"),
+        Interactive::Compose
+    );
+    assert_eq!(
+        crate::classify_interactive(":edit  3"),
+        Interactive::Edit("3")
+    );
+    assert_eq!(
+        crate::classify_interactive(":edit 3 new"),
+        Interactive::Line
+    );
+    assert_eq!(
+        crate::classify_interactive("first line\\"),
+        Interactive::Continued("first line")
+    );
+    assert_eq!(crate::classify_interactive(":show\\"), Interactive::Line);
+    assert_eq!(crate::classify_interactive("plain"), Interactive::Line);
+
+    // 行は改行で繋ぎ, 末尾の空行は落とす.
+    let lines = vec![
+        "a".to_string(),
+        "".to_string(),
+        "b".to_string(),
+        "".to_string(),
+    ];
+    assert_eq!(crate::repl::join_lines(lines.as_slice()), "a\n\nb");
+    assert_eq!(crate::repl::join_lines(&[]), "");
+
+    with_file("toml", |_| {
+        assert_eq!(run("init test"), 0);
+        crate::commands::post("a\nb".to_string());
+
+        // 編集中にnumが変わっても同じpostを指すよう, uuidで返す.
+        let (target, content) = crate::commands::content_of(&PostRef::Num(1)).unwrap();
+        assert_eq!(content, "a\nb");
+        assert!(matches!(&target, PostRef::Uuid(u) if u.len() == 32));
+        assert!(crate::commands::content_of(&PostRef::Num(2)).is_none());
+        assert_eq!(
+            crate::output::take_failure(),
+            Some(crate::output::Failure::NotFound)
+        );
+
+        // 編集中に他で変更されたら, 上書きしない.
+        assert_eq!(run("edit 1 changed by other"), 0);
+        crate::commands::edit_post(target.clone(), "composed".to_string(), Some("a\nb"));
+        assert_eq!(
+            crate::output::take_failure(),
+            Some(crate::output::Failure::Conflict)
+        );
+        assert_eq!(load().posts[0].content, "changed by other");
+
+        crate::commands::edit_post(target, "composed".to_string(), Some("changed by other"));
+        assert_eq!(crate::output::take_failure(), None);
+        assert_eq!(load().posts[0].content, "composed");
+    });
+}
+
+#[test]
+fn output_test() {
+    use crate::output::{self, Failure};
+
+    // 失敗の種類ごとの終了コードとjsonのcode.
+    let failures = [
+        Failure::Storage,
+        Failure::Usage,
+        Failure::NotFound,
+        Failure::Conflict,
+    ];
+    assert_eq!(
+        failures.iter().map(|v| v.exit_code()).collect::<Vec<_>>(),
+        vec![1, 2, 3, 4]
+    );
+    assert_eq!(
+        failures.iter().map(|v| v.code()).collect::<Vec<_>>(),
+        vec!["storage", "usage", "not_found", "conflict"]
+    );
+
+    // 成功と失敗の包み方. detailsとwarningsは有る時だけ付く.
+    assert_eq!(
+        output::ok_envelope(serde_json::json!({ "num": 1 })),
+        serde_json::json!({ "ok": true, "data": { "num": 1 } })
+    );
+    assert_eq!(
+        output::error_envelope(Failure::NotFound, "missing", serde_json::Value::Null),
+        serde_json::json!({ "ok": false, "error": { "code": "not_found", "message": "missing" } })
+    );
+    assert_eq!(
+        output::error_envelope(Failure::Storage, "broken", serde_json::json!([1])),
+        serde_json::json!({
+            "ok": false,
+            "error": { "code": "storage", "message": "broken", "details": [1] },
+        })
+    );
+    let warning = output::error_json(Failure::Storage, "journal");
+    assert_eq!(
+        output::with_warnings(output::ok_envelope(serde_json::Value::Null), vec![warning]),
+        serde_json::json!({
+            "ok": true,
+            "data": null,
+            "warnings": [{ "code": "storage", "message": "journal" }],
+        })
+    );
+
+    with_file("toml", |_| {
+        // 警告では終了コードは変わらないが, エラーでは変わる.
+        output::warn(Failure::Storage, "warned");
+        assert_eq!(output::take_failure(), None);
+        output::error(Failure::Conflict, "failed");
+        assert_eq!(output::take_failure(), Some(Failure::Conflict));
+        assert_eq!(output::take_failure(), None);
+    });
+}
+
+#[test]
+fn exit_status_test() {
+    with_file("toml", |path| {
+        // ファイルが無ければ1, 不明なコマンドや引数の誤りは2.
+        assert!(!path.exists());
+        assert_eq!(run("show"), 1);
+        assert_eq!(run("unknown"), 2);
+        assert_eq!(run("post "), 2);
+
+        assert_eq!(run("init test"), 0);
+        assert_eq!(run("remove x"), 2);
+
+        // 見つからないpostは3, 取り消すものが無ければ4.
+        assert_eq!(run("remove 99"), 3);
+        assert_eq!(run("undo"), 4);
+
+        // 内容の無いeditはpostを空にせずusageエラー.
+        assert_eq!(run("post hello"), 0);
+        assert_eq!(run("edit 1"), 2);
+        assert_eq!(run("edit 1 "), 2);
+        assert_eq!(run("edit"), 2);
+        assert_eq!(load().posts[0].content, "hello");
+        assert_eq!(run("remove 1"), 0);
+
+        // json形式でも終了コードは同じ.
+        crate::output::set_format(crate::output::Format::Json);
+        assert_eq!(run("remove 99"), 3);
+        assert_eq!(run("post hello"), 0);
+        assert_eq!(run("redo"), 4);
+        crate::output::set_format(crate::output::Format::Text);
+    });
+}